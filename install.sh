@@ -60,21 +60,7 @@ detect_and_configure_shell() {
             config_file="${HOME}/.bashrc"
             function_code=$(cat << 'EOF'
 # Worktree wrapper function - installed by worktree installer
-wt() {
-    local output
-    output=$(worktree "$@")
-    local exit_code=$?
-
-    # Check if the output contains a cd command
-    if echo "$output" | grep -q "^cd "; then
-        # Execute the cd command
-        eval "$output"
-    else
-        echo "$output"
-    fi
-
-    return $exit_code
-}
+eval "$(worktree shell-init bash)"
 EOF
 )
             ;;
@@ -82,21 +68,7 @@ EOF
             config_file="${HOME}/.zshrc"
             function_code=$(cat << 'EOF'
 # Worktree wrapper function - installed by worktree installer
-wt() {
-    local output
-    output=$(worktree "$@")
-    local exit_code=$?
-
-    # Check if the output contains a cd command
-    if echo "$output" | grep -q "^cd "; then
-        # Execute the cd command
-        eval "$output"
-    else
-        echo "$output"
-    fi
-
-    return $exit_code
-}
+eval "$(worktree shell-init zsh)"
 EOF
 )
             ;;
@@ -105,18 +77,7 @@ EOF
             mkdir -p "$(dirname "$config_file")"
             function_code=$(cat << 'EOF'
 # Worktree wrapper function - installed by worktree installer
-function wt
-    set output (worktree $argv)
-    set exit_code $status
-
-    if string match -q "cd *" $output
-        eval $output
-    else
-        echo $output
-    end
-
-    return $exit_code
-end
+worktree shell-init fish | source
 EOF
 )
             ;;
@@ -181,33 +142,12 @@ else
     echo ""
     echo -e "${YELLOW}⚠️  Manual configuration required${NC}"
     echo ""
-    echo "Add this function to your shell configuration:"
-    echo ""
-    echo "For Bash/Zsh (~/.bashrc or ~/.zshrc):"
-    echo "----------------------------------------"
-    echo 'wt() {'
-    echo '    local output'
-    echo '    output=$(worktree "$@")'
-    echo '    local exit_code=$?'
-    echo '    if echo "$output" | grep -q "^cd "; then'
-    echo '        eval "$output"'
-    echo '    else'
-    echo '        echo "$output"'
-    echo '    fi'
-    echo '    return $exit_code'
-    echo '}'
+    echo "Add the line for your shell to its configuration:"
     echo ""
-    echo "For Fish (~/.config/fish/config.fish):"
-    echo "----------------------------------------"
-    echo 'function wt'
-    echo '    set output (worktree $argv)'
-    echo '    set exit_code $status'
-    echo '    if string match -q "cd *" $output'
-    echo '        eval $output'
-    echo '    else'
-    echo '        echo $output'
-    echo '    end'
-    echo '    return $exit_code'
-    echo 'end'
+    echo "  Bash (~/.bashrc):        eval \"\$(worktree shell-init bash)\""
+    echo "  Zsh (~/.zshrc):          eval \"\$(worktree shell-init zsh)\""
+    echo "  Fish (config.fish):      worktree shell-init fish | source"
+    echo "  Nushell (config.nu):     source ~/.cache/wt.nu, after: worktree shell-init nushell | save -f ~/.cache/wt.nu"
+    echo "  PowerShell (\$PROFILE):  Invoke-Expression (& worktree shell-init powershell | Out-String)"
     echo ""
 fi
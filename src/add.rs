@@ -1,34 +1,167 @@
+use crate::config::Config;
+use crate::copy;
+use crate::forge::{self, Remote};
+use crate::git;
+use crate::hooks::{self, Hook};
+use crate::issue;
+use crate::meta::{Meta, PullRequestLink};
+use crate::seed;
+use crate::template;
+use crate::shell;
 use crate::utils;
+use crate::worktrees::{self, Worktree};
 use anyhow::{bail, Context, Result};
+use std::fs;
 use std::process::Command;
 
-pub fn execute(name: &str) -> Result<()> {
-    let root_dir = utils::get_root_dir()?;
-    let repo_name = utils::get_repo_name()?;
-    let current_dir = utils::get_current_dir()?;
+/// Where the branch for a new worktree comes from
+pub enum BranchSource {
+    /// Create a new branch from the given ref, or the configured base
+    New { from: Option<String> },
+    /// Use an existing local branch, or the branch of the same name on origin
+    Existing,
+    /// Create a local branch tracking the given remote branch, e.g. `origin/foo`
+    Track,
+    /// Check out the head of a pull request, given its number or URL
+    PullRequest { spec: String },
+    /// Create a new branch named after an issue, from the given ref or the configured base
+    Issue { id: String, from: Option<String> },
+}
 
-    // Construct the worktree path: {root_dir}/{repo_name}/{branch_name}
-    let worktree_path = root_dir.join(&repo_name).join(name);
+/// `name` is the branch to create or use; for a pull request or issue it is
+/// optional and overrides the branch name picked for it
+pub fn execute(name: Option<&str>, source: BranchSource, seed: bool) -> Result<()> {
+    let config = Config::load()?;
+    if seed && config.seed.is_empty() {
+        bail!("Nothing to seed. List directories in the config, e.g. seed = [\"target\"]");
+    }
 
-    eprintln!("Creating worktree at: {}", worktree_path.display());
+    let mut meta = Meta::new();
+    // An issue only picks the name; the branch is created like any new one
+    let mut issue_branch = None;
+    if let BranchSource::Issue { id, .. } = &source {
+        let found = issue::find(&config, &utils::get_main_worktree_path()?, id)?;
+        eprintln!("Issue {}: {}", found.id, found.title);
+        issue_branch = Some(template::issue_branch(&config, &found.id, &found.title)?);
+        meta.issue = Some(found);
+    }
+    let name = name.or(issue_branch.as_deref());
+    let required_name = || name.context("A branch name is required");
 
-    // Check if the branch exists
-    let branch_exists = Command::new("git")
-        .args(["show-ref", "--verify", &format!("refs/heads/{}", name)])
-        .output()
-        .context("Failed to check if branch exists")?
-        .status
-        .success();
+    let mut start_point = None;
+    // Where a pull request's branch pulls from, since it has no remote-tracking branch
+    let mut pull_request_ref = None;
+    let (branch, upstream) = match source {
+        BranchSource::New { from } | BranchSource::Issue { from, .. } => {
+            let name = required_name()?;
+            if local_branch_exists(name)? {
+                bail!("Branch '{}' already exists. Use --existing to create a worktree for it.", name);
+            }
+            let base = match from.or_else(|| config.base.clone()) {
+                Some(base) => base,
+                None => default_base()?,
+            };
+            // Make sure a remote base like origin/main is current before branching from it
+            if let Some((remote, branch)) = split_remote_ref(&base)? {
+                fetch(remote, branch);
+            }
+            let commit = git::backend().commit(&utils::get_current_dir()?, &base)?;
+            // Until the branch moves from here it has nothing to merge
+            meta.base_commit = Some(commit.hash.clone());
+            start_point = Some((base, commit));
+            (name.to_string(), None)
+        }
+        BranchSource::Existing => {
+            let name = required_name()?;
+            if !local_branch_exists(name)? {
+                fetch("origin", name);
+            }
+            let remote_ref = format!("origin/{}", name);
+            let upstream = remote_branch_exists(&remote_ref)?.then_some(remote_ref);
+            if upstream.is_none() && !local_branch_exists(name)? {
+                bail!("Branch '{}' not found locally or on origin", name);
+            }
+            (name.to_string(), upstream)
+        }
+        BranchSource::Track => {
+            let name = required_name()?;
+            let (remote, branch) = name
+                .split_once('/')
+                .with_context(|| format!("Expected a remote branch like origin/<branch>, got '{}'", name))?;
+            fetch(remote, branch);
+            if !remote_branch_exists(name)? {
+                bail!("Remote branch '{}' not found", name);
+            }
+            (branch.to_string(), Some(name.to_string()))
+        }
+        BranchSource::PullRequest { spec } => {
+            let (branch, refname, pr) = fetch_pull_request(&config, &spec, name)?;
+            meta.pull_request = Some(pr);
+            let commit = git::backend().commit(&utils::get_current_dir()?, &refname)?;
+            start_point = Some((refname.clone(), commit));
+            pull_request_ref = Some(refname);
+            (branch, None)
+        }
+    };
+
+    // If the branch is already checked out somewhere, just go there
+    let existing = worktrees::list()?;
+    if let Some(wt) = existing.iter().find(|wt| wt.branch.as_deref() == Some(branch.as_str())) {
+        eprintln!("Branch '{}' is already checked out at: {}", branch, wt.path.display());
+        shell::cd(&wt.path)?;
+        return Ok(());
+    }
+
+    // Construct the worktree path from the configured template
+    let worktree_path = template::worktree_path(&config, &branch)?;
+
+    // Names like feature/foo and feature-foo map to the same directory, and
+    // worktrees are told apart by their directory name
+    let dir_name = worktree_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let main_worktree_path = utils::get_main_worktree_path()?;
+    if let Some(wt) = existing
+        .iter()
+        .filter(|wt| wt.path != main_worktree_path)
+        .find(|wt| wt.path == worktree_path || wt.name() == dir_name)
+    {
+        bail!(
+            "Branch '{}' would use the worktree name {}, which is taken by {} at {}",
+            branch,
+            dir_name,
+            wt.branch.as_deref().map_or("a detached worktree".to_string(), |other| format!("branch '{}'", other)),
+            wt.path.display()
+        );
+    }
+    // git itself accepts an empty directory
+    let occupied = fs::read_dir(&worktree_path).map_or(worktree_path.exists(), |mut entries| entries.next().is_some());
+    if occupied {
+        bail!("Cannot create worktree for '{}': {} already exists", branch, worktree_path.display());
+    }
 
-    if branch_exists {
-        bail!("Branch already exists.");
+    eprintln!("Creating worktree at: {}", worktree_path.display());
+    if let Some((base, commit)) = &start_point {
+        eprintln!("Branching from {} ({} {})", base, commit.short_hash, commit.subject);
     }
 
     // Create the worktree
     let mut cmd = Command::new("git");
     cmd.arg("worktree").arg("add");
 
+    // The directory name may differ from the branch, so name the branch explicitly
+    let create_branch = !local_branch_exists(&branch)?;
+    if create_branch {
+        // A new branch must not end up tracking a remote base like origin/main
+        cmd.arg(if upstream.is_some() { "--track" } else { "--no-track" });
+        cmd.args(["-b", &branch]);
+    }
     cmd.arg(worktree_path.to_str().unwrap());
+    if create_branch {
+        if let Some(start) = upstream.as_ref().or(start_point.as_ref().map(|(base, _)| base)) {
+            cmd.arg(start);
+        }
+    } else {
+        cmd.arg(&branch);
+    }
 
     let output = cmd.output().context("Failed to execute git worktree add")?;
 
@@ -39,11 +172,224 @@ pub fn execute(name: &str) -> Result<()> {
 
     eprintln!("{}", String::from_utf8_lossy(&output.stderr));
 
-    // Copy .env file from current directory to the new worktree
-    utils::copy_env_file(&current_dir, &worktree_path)?;
+    // An existing local branch may not track its remote counterpart yet
+    if let (false, Some(upstream)) = (create_branch, &upstream) {
+        set_upstream(&branch, upstream)?;
+    }
+    if let Some(refname) = &pull_request_ref {
+        set_pull_request_upstream(&branch, refname)?;
+    }
+
+    // HEAD says little later on; the branch it was on says more
+    meta.branch = Some(branch.clone());
+    meta.base = match upstream.or(start_point.map(|(base, _)| base)) {
+        Some(base) if base == "HEAD" => git::backend()
+            .current_branch(&utils::get_current_dir()?)
+            .ok()
+            .flatten()
+            .or(Some(base)),
+        base => base,
+    };
+    if let Err(err) = meta.save(dir_name) {
+        eprintln!("Warning: {:#}", err);
+    }
 
-    // Print the cd command for the shell to execute
-    utils::print_cd_command(&worktree_path);
+    // Bring over untracked files like .env from the main worktree
+    copy::carry_over(&config.copy, &main_worktree_path, &worktree_path)?;
+
+    // Warm build directories before hooks that build anything
+    if seed {
+        seed::seed(&config.seed, &main_worktree_path, &worktree_path)?;
+    }
+
+    let wt = Worktree {
+        path: worktree_path.clone(),
+        branch: Some(branch.clone()),
+        ..Default::default()
+    };
+    hooks::run(&config, Hook::PostCreate, &wt, &main_worktree_path)
+        .with_context(|| format!("Worktree created at {}, but setting it up failed", worktree_path.display()))?;
+
+    // Have the shell wrapper change directory
+    shell::cd(&worktree_path)?;
+
+    Ok(())
+}
+
+/// Check whether a local branch exists
+fn local_branch_exists(branch: &str) -> Result<bool> {
+    ref_exists(&format!("refs/heads/{}", branch))
+}
+
+/// Check whether a remote-tracking branch like `origin/foo` exists
+fn remote_branch_exists(remote_branch: &str) -> Result<bool> {
+    ref_exists(&format!("refs/remotes/{}", remote_branch))
+}
+
+fn ref_exists(refname: &str) -> Result<bool> {
+    git::backend()
+        .ref_exists(refname)
+        .context("Failed to check if branch exists")
+}
+
+/// Fetch a single branch so that its remote-tracking ref is up to date.
+/// Failures only warn, since the ref may already be available locally.
+fn fetch(remote: &str, branch: &str) {
+    eprintln!("Fetching {}/{}", remote, branch);
+    let output = Command::new("git")
+        .args(["fetch", remote, branch])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            eprintln!("Warning: Failed to fetch {}/{}: {}", remote, branch, stderr.trim());
+        }
+        Err(err) => eprintln!("Warning: Failed to fetch {}/{}: {}", remote, branch, err),
+    }
+}
+
+/// Where new branches start unless told otherwise: the upstream of the main
+/// worktree's branch, e.g. origin/main, or the branch itself if it has none.
+/// Not HEAD, which inside another worktree would stack the branch on its work.
+fn default_base() -> Result<String> {
+    let backend = git::backend();
+    let main_worktree_path = utils::get_main_worktree_path()?;
+    let Some(branch) = backend.current_branch(&main_worktree_path)? else {
+        return Ok(backend.commit(&main_worktree_path, "HEAD")?.hash);
+    };
+    Ok(backend.upstream(&branch)?.unwrap_or(branch))
+}
+
+/// Split `origin/main` into remote and branch if it names a remote-tracking branch
+fn split_remote_ref(refname: &str) -> Result<Option<(&str, &str)>> {
+    let Some((remote, branch)) = refname.split_once('/') else {
+        return Ok(None);
+    };
+
+    let is_remote = git::backend().remotes()?.iter().any(|r| r == remote);
+    Ok(is_remote.then_some((remote, branch)))
+}
+
+/// The pull request number in `spec`, which is a number like `1234` or
+/// `#1234`, or a URL, and the repository path a URL names
+fn parse_pull_request(spec: &str) -> Result<(u64, Option<String>)> {
+    if let Ok(number) = spec.trim_start_matches(['#', '!']).parse() {
+        return Ok((number, None));
+    }
+
+    // e.g. https://github.com/owner/repo/pull/1234/files or
+    // https://gitlab.com/group/repo/-/merge_requests/1234
+    let path = spec.split_once("://").and_then(|(_, rest)| rest.split_once('/')).map(|(_, path)| path);
+    let segments: Vec<&str> = path.unwrap_or_default().split('/').collect();
+    for (i, pair) in segments.windows(2).enumerate() {
+        if !matches!(pair[0], "pull" | "pulls" | "merge_requests") {
+            continue;
+        }
+        if let Ok(number) = pair[1].parse() {
+            let repo: Vec<&str> = segments[..i].iter().copied().filter(|segment| *segment != "-").collect();
+            return Ok((number, Some(repo.join("/"))));
+        }
+    }
+    bail!("Expected a pull request number or URL, got '{}'", spec)
+}
+
+/// Fetch the head of the pull request `spec` from origin to the same ref
+/// locally. Returns the branch to check it out on, `name` or else its head
+/// branch or `pr-<number>`, the fetched ref, and the pull request to remember.
+fn fetch_pull_request(config: &Config, spec: &str, name: Option<&str>) -> Result<(String, String, PullRequestLink)> {
+    let (number, repo) = parse_pull_request(spec)?;
+    let origin = git::backend().remote_url("origin")?.context("There is no origin remote to fetch from")?;
+    if let (Some(repo), Some(remote)) = (&repo, Remote::parse(&origin)) {
+        if !repo.eq_ignore_ascii_case(&remote.path) {
+            bail!("{} is in {}, but origin is {}", spec, repo, remote.path);
+        }
+    }
+
+    let forge = forge::detect(config, &utils::get_main_worktree_path()?)?;
+    let refname = match &forge {
+        Some(forge) => forge.pull_request_ref(number),
+        None => format!("refs/pull/{}/head", number),
+    };
+    // Knowing the head branch only improves the name, so carry on without it
+    let pr = forge.as_ref().and_then(|forge| match forge.pull_request_by_number(number) {
+        Ok(pr) => Some(pr),
+        Err(err) => {
+            eprintln!("Warning: Could not look up pull request {}: {:#}", number, err);
+            None
+        }
+    });
+    match &pr {
+        Some(pr) => eprintln!("Reviewing {}: {} ({})", pr.reference, pr.title, pr.head),
+        None => eprintln!("Reviewing pull request {}", number),
+    }
+
+    eprintln!("Fetching {} from origin", refname);
+    let output = Command::new("git")
+        .args(["fetch", "origin", &format!("+{}:{}", refname, refname)])
+        .output()
+        .context("Failed to execute git fetch")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("Failed to fetch pull request {} from origin: {}", number, stderr.trim());
+    }
+
+    // A branch already checked out for this pull request is reused
+    let available = |branch: &str| -> Result<bool> {
+        Ok(!local_branch_exists(branch)? || git::backend().merge_ref(branch)?.as_deref() == Some(refname.as_str()))
+    };
+    let branch = match (name, pr.as_ref().map(|pr| pr.head.as_str())) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(head)) if available(head)? => head.to_string(),
+        (None, _) => format!("pr-{}", number),
+    };
+    if !available(&branch)? {
+        bail!("Branch '{}' already exists. Pass another name for the worktree.", branch);
+    }
+    let link = match &pr {
+        Some(pr) => PullRequestLink::from(pr),
+        None => PullRequestLink {
+            number,
+            reference: format!("#{}", number),
+            url: spec.contains("://").then(|| spec.to_string()),
+        },
+    };
+    Ok((branch, refname, link))
+}
+
+/// Make `branch` pull from the pull request head `refname` on origin, which
+/// also marks it as a pull request checkout for `wt rm`
+fn set_pull_request_upstream(branch: &str, refname: &str) -> Result<()> {
+    for (key, value) in [("remote", "origin"), ("merge", refname)] {
+        let output = Command::new("git")
+            .args(["config", &format!("branch.{}.{}", branch, key), value])
+            .output()
+            .context("Failed to execute git config")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to set the upstream of {}: {}", branch, stderr.trim());
+        }
+    }
+    Ok(())
+}
+
+/// Make `branch` track `upstream` unless it already tracks something
+fn set_upstream(branch: &str, upstream: &str) -> Result<()> {
+    if git::backend().upstream(branch)?.is_some() {
+        return Ok(());
+    }
+
+    let output = Command::new("git")
+        .args(["branch", &format!("--set-upstream-to={}", upstream), branch])
+        .output()
+        .context("Failed to execute git branch --set-upstream-to")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("Failed to set upstream of {} to {}: {}", branch, upstream, stderr);
+    }
 
+    eprintln!("Branch '{}' now tracks {}", branch, upstream);
     Ok(())
 }
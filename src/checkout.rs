@@ -1,79 +1,104 @@
-use anyhow::{Context, Result, bail};
-use std::path::PathBuf;
-use std::process::Command;
-use crate::utils;
+use anyhow::{bail, Context, Result};
+use dialoguer::console::Term;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::FuzzySelect;
+use crate::config::Config;
+use crate::git;
+use crate::list;
+use crate::shell;
+use crate::worktrees::{self, Worktree};
 
-pub fn execute(name: &str) -> Result<()> {
-    let worktrees = list_worktrees()?;
+pub fn execute(name: Option<&str>) -> Result<()> {
+    let selected = match name {
+        Some(name) => resolve(name)?,
+        None => pick(worktrees::list()?, "")?,
+    };
+    // The picker was cancelled
+    let Some(wt) = selected else {
+        return Ok(());
+    };
 
-    // Find matching worktree by directory name or branch name
-    let matching = worktrees.iter().find(|wt| {
-        wt.dir_name == name || wt.branch.as_deref() == Some(name)
-    });
+    eprintln!("Switching to worktree: {}", wt.path.display());
+    shell::cd(&wt.path)?;
+    Ok(())
+}
 
-    match matching {
-        Some(wt) => {
-            eprintln!("Switching to worktree: {}", wt.path.display());
-            utils::print_cd_command(&wt.path);
-            Ok(())
-        }
-        None => {
-            bail!("Worktree '{}' not found. Run 'wt list' to see available worktrees.", name);
-        }
+/// Find the worktree `name` refers to: an exact match, else the only partial
+/// match, else whichever of the partial matches the user picks
+fn resolve(name: &str) -> Result<Option<Worktree>> {
+    let config = Config::load()?;
+    if let Some(wt) = worktrees::find(&config, name)? {
+        return Ok(Some(wt));
     }
-}
 
-struct Worktree {
-    path: PathBuf,
-    dir_name: String,
-    branch: Option<String>,
+    let mut matches = worktrees::search(worktrees::list()?, name);
+    match matches.len() {
+        0 => bail!("Worktree '{}' not found. Run 'wt list' to see available worktrees.", name),
+        1 => Ok(matches.pop()),
+        _ if !Term::stderr().is_term() => bail!(
+            "'{}' matches several worktrees: {}",
+            name,
+            matches.iter().map(|wt| wt.name()).collect::<Vec<_>>().join(", ")
+        ),
+        _ => pick(matches, name),
+    }
 }
 
-fn list_worktrees() -> Result<Vec<Worktree>> {
-    let output = Command::new("git")
-        .args(["worktree", "list"])
-        .output()
-        .context("Failed to execute git worktree list")?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!("Failed to list worktrees: {}", stderr);
+/// Let the user choose among `worktrees` with a fuzzy finder drawn on stderr,
+/// so stdout stays free for the cd command. `None` if they cancel.
+fn pick(worktrees: Vec<Worktree>, query: &str) -> Result<Option<Worktree>> {
+    if !Term::stderr().is_term() {
+        bail!("No worktree name given and no terminal to pick one from");
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut worktrees = Vec::new();
-
-    for line in stdout.lines() {
-        if line.is_empty() {
-            continue;
-        }
+    let items = describe(&worktrees);
+    let selection = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Worktree")
+        .with_initial_text(query)
+        .items(&items)
+        .default(0)
+        .interact_opt()
+        .context("Failed to read selection")?;
 
-        // Parse lines like: /path/to/worktree  abc1234 [branch-name]
-        // or: /path/to/worktree  abc1234 (bare)
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() {
-            continue;
-        }
-
-        let path = PathBuf::from(parts[0]);
-        let dir_name = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("")
-            .to_string();
-
-        // Extract branch name from [branch] if present
-        let branch = line
-            .find('[')
-            .and_then(|start| line.find(']').map(|end| (start, end)))
-            .map(|(start, end)| line[start + 1..end].to_string());
+    Ok(selection.map(|index| worktrees[index].clone()))
+}
 
-        worktrees.push(Worktree {
-            path,
-            dir_name,
-            branch,
-        });
-    }
+/// One aligned line per worktree: name, branch, changes and last commit
+fn describe(worktrees: &[Worktree]) -> Vec<String> {
+    let backend = git::backend();
+    let rows: Vec<[String; 4]> = worktrees
+        .iter()
+        .map(|wt| {
+            let branch = wt.branch.clone().unwrap_or_else(|| "(detached)".to_string());
+            let status = if wt.prunable.is_some() { None } else { Some(backend.status(&wt.path)) };
+            let changes = match status {
+                None => "missing".to_string(),
+                Some(Ok(status)) if status.dirty == 0 && status.untracked.is_empty() => "clean".to_string(),
+                Some(Ok(_)) => "dirty".to_string(),
+                Some(Err(_)) => String::new(),
+            };
+            let last_commit = backend
+                .commit(&wt.path, "HEAD")
+                .map(|commit| format!("{}  {}", list::format_age(commit.timestamp), commit.subject))
+                .unwrap_or_default();
+            [wt.name().to_string(), branch, changes, last_commit]
+        })
+        .collect();
 
-    Ok(worktrees)
+    let width = |column: usize| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0);
+    let widths = [width(0), width(1), width(2)];
+    rows.iter()
+        .map(|row| {
+            format!(
+                "{:w0$}  {:w1$}  {:w2$}  {}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2]
+            )
+        })
+        .collect()
 }
@@ -0,0 +1,64 @@
+//! Dynamic shell completion.
+//!
+//! The registration script printed by `wt completions <shell>` calls back
+//! into the binary with `WORKTREE_COMPLETE` set on every Tab press, so
+//! worktree names and refs are always current.
+
+use anyhow::{Context, Result};
+use clap_complete::env::Shells;
+use clap_complete::CompletionCandidate;
+use std::collections::BTreeSet;
+use std::io;
+use crate::git;
+use crate::worktrees;
+
+/// Environment variable that switches the binary into completion mode
+pub const COMPLETE_VAR: &str = "WORKTREE_COMPLETE";
+
+/// Shells the registration script can be written for
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell", "elvish"];
+
+/// Print the script that registers completion for both `worktree` and the `wt` function
+pub fn execute(shell: &str) -> Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell)
+        .with_context(|| format!("Unsupported shell: {}", shell))?;
+
+    let mut stdout = io::stdout();
+    for bin in ["worktree", "wt"] {
+        completer
+            .write_registration(COMPLETE_VAR, "worktree", bin, "worktree", &mut stdout)
+            .context("Failed to write completion script")?;
+    }
+    Ok(())
+}
+
+/// Worktree directory names and branch names, for commands taking a worktree
+pub fn worktree_names() -> Vec<CompletionCandidate> {
+    let Ok(worktrees) = worktrees::list() else {
+        return Vec::new();
+    };
+
+    let mut seen = BTreeSet::new();
+    let mut candidates = Vec::new();
+    for wt in &worktrees {
+        let help = wt.path.display().to_string();
+        for name in [Some(wt.name()), wt.branch.as_deref()].into_iter().flatten() {
+            if !name.is_empty() && seen.insert(name.to_string()) {
+                candidates.push(CompletionCandidate::new(name).help(Some(help.clone().into())));
+            }
+        }
+    }
+    candidates
+}
+
+/// Branches, remote-tracking branches and tags
+pub fn refs() -> Vec<CompletionCandidate> {
+    git::backend()
+        .refs()
+        .unwrap_or_default()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
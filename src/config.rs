@@ -0,0 +1,585 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::utils;
+
+/// Name of the per-repository config file, read from the main worktree
+pub const REPO_CONFIG_FILE: &str = ".worktree.toml";
+
+const DEFAULT_PATH_TEMPLATE: &str = "{root}/{repo}/{branch_slug}";
+const DEFAULT_MERGE_STRATEGY: &str = "squash";
+const DEFAULT_ISSUE_BRANCH: &str = "{user}/{issue}-{slug}";
+
+/// Where a resolved config value came from
+#[derive(Debug, Clone)]
+pub enum Source {
+    Default,
+    Global(PathBuf),
+    Repo(PathBuf),
+    Env(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Global(path) => write!(f, "global {}", path.display()),
+            Source::Repo(path) => write!(f, "repo {}", path.display()),
+            Source::Env(var) => write!(f, "env {}", var),
+        }
+    }
+}
+
+/// The contents of a single config file. Every key is optional so that
+/// layers only override what they actually set.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct ConfigFile {
+    root_dir: Option<String>,
+    path_template: Option<String>,
+    base: Option<String>,
+    copy: Option<Vec<CopyRule>>,
+    seed: Option<Vec<String>>,
+    hooks: HooksFile,
+    merge: MergeFile,
+    forge: ForgeFile,
+    pr: PrFile,
+    issue: IssueFile,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct ForgeFile {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    api_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct HooksFile {
+    post_create: Option<Vec<HookCommand>>,
+    pre_remove: Option<Vec<HookCommand>>,
+    post_remove: Option<Vec<HookCommand>>,
+    post_merge: Option<Vec<HookCommand>>,
+    on_failure: Option<OnFailure>,
+}
+
+/// A copy entry: either a plain pattern or a table choosing the mode
+#[derive(Debug, Deserialize)]
+#[serde(untagged, expecting = "a pattern, or a table like { pattern = \"...\", mode = \"symlink\" }")]
+enum CopyEntry {
+    Pattern(String),
+    Table(CopyTable),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct CopyTable {
+    pattern: String,
+    #[serde(default)]
+    mode: CopyMode,
+}
+
+/// A hook entry: either a plain command or a table with its own failure policy
+#[derive(Debug, Deserialize)]
+#[serde(untagged, expecting = "a command, or a table like { run = \"...\", on-failure = \"warn\" }")]
+enum HookEntry {
+    Command(String),
+    Table(HookTable),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct HookTable {
+    run: String,
+    on_failure: Option<OnFailure>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct MergeFile {
+    strategy: Option<String>,
+    base: Option<String>,
+    squash_message: Option<String>,
+    verify: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct PrFile {
+    title: Option<String>,
+    body: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct IssueFile {
+    branch: Option<String>,
+    cache: Option<String>,
+}
+
+/// Files matching `pattern` in the main worktree are brought into new worktrees
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "CopyEntry")]
+pub struct CopyRule {
+    /// Glob relative to the main worktree, e.g. `.env*` or `config/master.key`
+    pub pattern: String,
+    pub mode: CopyMode,
+}
+
+impl From<CopyEntry> for CopyRule {
+    fn from(entry: CopyEntry) -> Self {
+        match entry {
+            CopyEntry::Pattern(pattern) => CopyRule { pattern, mode: CopyMode::default() },
+            CopyEntry::Table(table) => CopyRule { pattern: table.pattern, mode: table.mode },
+        }
+    }
+}
+
+/// How a matched file or directory gets into the new worktree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CopyMode {
+    /// An independent copy
+    #[default]
+    Copy,
+    /// A symlink to the original in the main worktree
+    Symlink,
+    /// Hard links to the original files
+    Hardlink,
+    /// A copy-on-write clone where the filesystem supports it, a copy otherwise
+    Reflink,
+}
+
+impl fmt::Display for CopyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CopyMode::Copy => write!(f, "copy"),
+            CopyMode::Symlink => write!(f, "symlink"),
+            CopyMode::Hardlink => write!(f, "hardlink"),
+            CopyMode::Reflink => write!(f, "reflink"),
+        }
+    }
+}
+
+/// Commands to run at points in a worktree's life
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pub post_create: Vec<HookCommand>,
+    pub pre_remove: Vec<HookCommand>,
+    pub post_remove: Vec<HookCommand>,
+    pub post_merge: Vec<HookCommand>,
+    /// Policy for commands that don't set their own
+    pub on_failure: OnFailure,
+}
+
+/// A shell command run as a hook
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "HookEntry")]
+pub struct HookCommand {
+    pub run: String,
+    /// Overrides `hooks.on-failure` for this command
+    pub on_failure: Option<OnFailure>,
+}
+
+impl From<HookEntry> for HookCommand {
+    fn from(entry: HookEntry) -> Self {
+        match entry {
+            HookEntry::Command(run) => HookCommand { run, on_failure: None },
+            HookEntry::Table(table) => HookCommand { run: table.run, on_failure: table.on_failure },
+        }
+    }
+}
+
+/// What to do when a hook command fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnFailure {
+    /// Stop running hooks and fail the command; a failing pre-remove hook
+    /// keeps the worktree
+    #[default]
+    Abort,
+    /// Print a warning and carry on
+    Warn,
+    /// Carry on silently
+    Ignore,
+}
+
+impl fmt::Display for OnFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OnFailure::Abort => write!(f, "abort"),
+            OnFailure::Warn => write!(f, "warn"),
+            OnFailure::Ignore => write!(f, "ignore"),
+        }
+    }
+}
+
+/// Resolved configuration.
+///
+/// Precedence, highest first: environment, repo `.worktree.toml`,
+/// global `~/.config/worktree/config.toml`, built-in defaults.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub root_dir: PathBuf,
+    pub path_template: String,
+    /// Ref new branches start from unless `--from` is given, instead of the
+    /// main worktree's branch (or its upstream)
+    pub base: Option<String>,
+    /// What to bring over from the main worktree into new worktrees
+    pub copy: Vec<CopyRule>,
+    /// Build directories `wt add --seed` clones from the main worktree
+    pub seed: Vec<String>,
+    pub hooks: Hooks,
+    pub merge_strategy: String,
+    /// Branch local merges go into, instead of whatever the main worktree has checked out
+    pub merge_base: Option<String>,
+    /// Template for the title of local squash merges, instead of the first commit's subject
+    pub merge_squash_message: Option<String>,
+    /// Commands `wt merge` runs before landing anything, such as `cargo test`
+    pub merge_verify: Vec<String>,
+    /// `github`, `gitlab`, `gitea` or `none`, instead of guessing from the origin URL
+    pub forge: Option<String>,
+    /// Base URL of the forge's REST API, for self-hosted instances
+    pub forge_api_url: Option<String>,
+    /// Template for the title of pull requests `wt pr` creates
+    pub pr_title: Option<String>,
+    /// Template for the body of pull requests `wt pr` creates
+    pub pr_body: Option<String>,
+    /// Template for the names of branches `wt add --issue` creates
+    pub issue_branch: String,
+    /// TOML file of ticket titles, for when the forge can't be asked
+    pub issue_cache: Option<PathBuf>,
+    sources: BTreeMap<&'static str, Source>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            root_dir: home_dir().join("worktrees"),
+            path_template: DEFAULT_PATH_TEMPLATE.to_string(),
+            base: None,
+            copy: vec![CopyRule {
+                pattern: ".env".to_string(),
+                mode: CopyMode::Copy,
+            }],
+            seed: Vec::new(),
+            hooks: Hooks::default(),
+            merge_strategy: DEFAULT_MERGE_STRATEGY.to_string(),
+            merge_base: None,
+            merge_squash_message: None,
+            merge_verify: Vec::new(),
+            forge: None,
+            forge_api_url: None,
+            pr_title: None,
+            pr_body: None,
+            issue_branch: DEFAULT_ISSUE_BRANCH.to_string(),
+            issue_cache: None,
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Load and merge every config layer
+    pub fn load() -> Result<Config> {
+        let mut config = Config::default();
+
+        if let Some(path) = global_config_path() {
+            if path.exists() {
+                let file = read_config_file(&path)?;
+                config.apply(file, Source::Global(path));
+            }
+        }
+
+        // Outside a repository there is simply no repo layer
+        if let Ok(main_worktree_path) = utils::get_main_worktree_path() {
+            let path = main_worktree_path.join(REPO_CONFIG_FILE);
+            if path.exists() {
+                let file = read_config_file(&path)?;
+                config.apply(file, Source::Repo(path));
+            }
+        }
+
+        config.apply_env();
+        Ok(config)
+    }
+
+    /// Where the value for `key` came from
+    pub fn source(&self, key: &str) -> Source {
+        self.sources.get(key).cloned().unwrap_or(Source::Default)
+    }
+
+    fn apply(&mut self, file: ConfigFile, source: Source) {
+        if let Some(root_dir) = file.root_dir {
+            self.root_dir = expand_home(&root_dir);
+            self.sources.insert("root-dir", source.clone());
+        }
+        if let Some(path_template) = file.path_template {
+            self.path_template = path_template;
+            self.sources.insert("path-template", source.clone());
+        }
+        if let Some(base) = file.base {
+            self.base = Some(base);
+            self.sources.insert("base", source.clone());
+        }
+        if let Some(copy) = file.copy {
+            self.copy = copy;
+            self.sources.insert("copy", source.clone());
+        }
+        if let Some(seed) = file.seed {
+            self.seed = seed;
+            self.sources.insert("seed", source.clone());
+        }
+        let hooks = [
+            ("hooks.post-create", file.hooks.post_create, &mut self.hooks.post_create),
+            ("hooks.pre-remove", file.hooks.pre_remove, &mut self.hooks.pre_remove),
+            ("hooks.post-remove", file.hooks.post_remove, &mut self.hooks.post_remove),
+            ("hooks.post-merge", file.hooks.post_merge, &mut self.hooks.post_merge),
+        ];
+        for (key, commands, hook) in hooks {
+            if let Some(commands) = commands {
+                *hook = commands;
+                self.sources.insert(key, source.clone());
+            }
+        }
+        if let Some(on_failure) = file.hooks.on_failure {
+            self.hooks.on_failure = on_failure;
+            self.sources.insert("hooks.on-failure", source.clone());
+        }
+        if let Some(strategy) = file.merge.strategy {
+            self.merge_strategy = strategy;
+            self.sources.insert("merge.strategy", source.clone());
+        }
+        if let Some(base) = file.merge.base {
+            self.merge_base = Some(base);
+            self.sources.insert("merge.base", source.clone());
+        }
+        if let Some(squash_message) = file.merge.squash_message {
+            self.merge_squash_message = Some(squash_message);
+            self.sources.insert("merge.squash-message", source.clone());
+        }
+        if let Some(verify) = file.merge.verify {
+            self.merge_verify = verify;
+            self.sources.insert("merge.verify", source.clone());
+        }
+        if let Some(kind) = file.forge.kind {
+            self.forge = Some(kind);
+            self.sources.insert("forge.type", source.clone());
+        }
+        if let Some(api_url) = file.forge.api_url {
+            self.forge_api_url = Some(api_url);
+            self.sources.insert("forge.api-url", source.clone());
+        }
+        if let Some(title) = file.pr.title {
+            self.pr_title = Some(title);
+            self.sources.insert("pr.title", source.clone());
+        }
+        if let Some(body) = file.pr.body {
+            self.pr_body = Some(body);
+            self.sources.insert("pr.body", source.clone());
+        }
+        if let Some(branch) = file.issue.branch {
+            self.issue_branch = branch;
+            self.sources.insert("issue.branch", source.clone());
+        }
+        if let Some(cache) = file.issue.cache {
+            self.issue_cache = Some(expand_home(&cache));
+            self.sources.insert("issue.cache", source);
+        }
+    }
+
+    fn apply_env(&mut self) {
+        if let Ok(root_dir) = env::var("WORKTREE_ROOT_DIR") {
+            self.root_dir = expand_home(&root_dir);
+            self.sources.insert("root-dir", Source::Env("WORKTREE_ROOT_DIR"));
+        }
+        if let Ok(path_template) = env::var("WORKTREE_PATH_TEMPLATE") {
+            self.path_template = path_template;
+            self.sources.insert("path-template", Source::Env("WORKTREE_PATH_TEMPLATE"));
+        }
+        if let Ok(base) = env::var("WORKTREE_BASE") {
+            self.base = Some(base);
+            self.sources.insert("base", Source::Env("WORKTREE_BASE"));
+        }
+        if let Ok(strategy) = env::var("WORKTREE_MERGE_STRATEGY") {
+            self.merge_strategy = strategy;
+            self.sources.insert("merge.strategy", Source::Env("WORKTREE_MERGE_STRATEGY"));
+        }
+        if let Ok(base) = env::var("WORKTREE_MERGE_BASE") {
+            self.merge_base = Some(base);
+            self.sources.insert("merge.base", Source::Env("WORKTREE_MERGE_BASE"));
+        }
+        if let Ok(squash_message) = env::var("WORKTREE_MERGE_SQUASH_MESSAGE") {
+            self.merge_squash_message = Some(squash_message);
+            self.sources.insert("merge.squash-message", Source::Env("WORKTREE_MERGE_SQUASH_MESSAGE"));
+        }
+        if let Ok(kind) = env::var("WORKTREE_FORGE") {
+            self.forge = Some(kind);
+            self.sources.insert("forge.type", Source::Env("WORKTREE_FORGE"));
+        }
+        if let Ok(api_url) = env::var("WORKTREE_FORGE_API_URL") {
+            self.forge_api_url = Some(api_url);
+            self.sources.insert("forge.api-url", Source::Env("WORKTREE_FORGE_API_URL"));
+        }
+        if let Ok(title) = env::var("WORKTREE_PR_TITLE") {
+            self.pr_title = Some(title);
+            self.sources.insert("pr.title", Source::Env("WORKTREE_PR_TITLE"));
+        }
+        if let Ok(branch) = env::var("WORKTREE_ISSUE_BRANCH") {
+            self.issue_branch = branch;
+            self.sources.insert("issue.branch", Source::Env("WORKTREE_ISSUE_BRANCH"));
+        }
+        if let Ok(cache) = env::var("WORKTREE_ISSUE_CACHE") {
+            self.issue_cache = Some(expand_home(&cache));
+            self.sources.insert("issue.cache", Source::Env("WORKTREE_ISSUE_CACHE"));
+        }
+    }
+}
+
+/// Print every resolved value along with where it came from
+pub fn show() -> Result<()> {
+    let config = Config::load()?;
+
+    let rows = [
+        ("root-dir", format!("{:?}", config.root_dir.display().to_string())),
+        ("path-template", format!("{:?}", config.path_template)),
+        (
+            "base",
+            match &config.base {
+                Some(base) => format!("{:?}", base),
+                None => "(upstream of the main worktree's branch)".to_string(),
+            },
+        ),
+        ("copy", format_copy(&config.copy)),
+        ("seed", format!("{:?}", config.seed)),
+        ("hooks.post-create", format_hook(&config.hooks.post_create)),
+        ("hooks.pre-remove", format_hook(&config.hooks.pre_remove)),
+        ("hooks.post-remove", format_hook(&config.hooks.post_remove)),
+        ("hooks.post-merge", format_hook(&config.hooks.post_merge)),
+        ("hooks.on-failure", format!("{:?}", config.hooks.on_failure.to_string())),
+        ("merge.strategy", format!("{:?}", config.merge_strategy)),
+        (
+            "merge.base",
+            match &config.merge_base {
+                Some(base) => format!("{:?}", base),
+                None => "(branch of the main worktree)".to_string(),
+            },
+        ),
+        (
+            "merge.squash-message",
+            match &config.merge_squash_message {
+                Some(template) => format!("{:?}", template),
+                None => "(first commit's subject)".to_string(),
+            },
+        ),
+        ("merge.verify", format!("{:?}", config.merge_verify)),
+        (
+            "forge.type",
+            match &config.forge {
+                Some(kind) => format!("{:?}", kind),
+                None => "(from the origin URL)".to_string(),
+            },
+        ),
+        (
+            "forge.api-url",
+            match &config.forge_api_url {
+                Some(url) => format!("{:?}", url),
+                None => "(the forge's default)".to_string(),
+            },
+        ),
+        (
+            "pr.title",
+            match &config.pr_title {
+                Some(template) => format!("{:?}", template),
+                None => "(first commit's subject)".to_string(),
+            },
+        ),
+        (
+            "pr.body",
+            match &config.pr_body {
+                Some(template) => format!("{:?}", template),
+                None => "(the branch's commits)".to_string(),
+            },
+        ),
+        ("issue.branch", format!("{:?}", config.issue_branch)),
+        (
+            "issue.cache",
+            match &config.issue_cache {
+                Some(path) => format!("{:?}", path.display().to_string()),
+                None => "(none)".to_string(),
+            },
+        ),
+    ];
+
+    let key_width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    let value_width = rows.iter().map(|(_, value)| value.len()).max().unwrap_or(0);
+
+    for (key, value) in &rows {
+        println!(
+            "{:key_width$} = {:value_width$}  # {}",
+            key,
+            value,
+            config.source(key),
+        );
+    }
+
+    Ok(())
+}
+
+/// Show copy rules as a list, noting any mode other than plain copying
+fn format_copy(rules: &[CopyRule]) -> String {
+    let rules: Vec<String> = rules
+        .iter()
+        .map(|rule| match rule.mode {
+            CopyMode::Copy => format!("{:?}", rule.pattern),
+            mode => format!("{:?} ({})", rule.pattern, mode),
+        })
+        .collect();
+    format!("[{}]", rules.join(", "))
+}
+
+/// Show hook commands as a list, noting any per-command failure policy
+fn format_hook(commands: &[HookCommand]) -> String {
+    let commands: Vec<String> = commands
+        .iter()
+        .map(|command| match command.on_failure {
+            Some(on_failure) => format!("{:?} (on-failure: {})", command.run, on_failure),
+            None => format!("{:?}", command.run),
+        })
+        .collect();
+    format!("[{}]", commands.join(", "))
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Invalid config file {}", path.display()))
+}
+
+fn global_config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("worktree").join("config.toml"))
+}
+
+fn home_dir() -> PathBuf {
+    env::var_os("HOME").map(PathBuf::from).unwrap_or_default()
+}
+
+/// Expand a leading `~` to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~") {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            home_dir().join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
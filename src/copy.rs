@@ -0,0 +1,109 @@
+//! Bringing files git doesn't track, like `.env` or `node_modules`, from the
+//! main worktree into a new one.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Component, Path};
+use crate::config::{CopyMode, CopyRule};
+
+/// Bring everything matching `rules` from the main worktree into `worktree_path`.
+/// Paths that already exist in the new worktree, such as tracked files, are left alone.
+pub fn carry_over(rules: &[CopyRule], main_worktree_path: &Path, worktree_path: &Path) -> Result<()> {
+    // The main worktree's path may itself contain glob metacharacters
+    let root = glob::Pattern::escape(&main_worktree_path.to_string_lossy());
+
+    for rule in rules {
+        let pattern = format!("{}/{}", root, rule.pattern.trim_start_matches('/'));
+        let matches = glob::glob(&pattern)
+            .with_context(|| format!("Invalid copy pattern: {}", rule.pattern))?;
+
+        for src in matches {
+            let src = match src {
+                Ok(src) => src,
+                Err(err) => {
+                    eprintln!("Warning: skipping {}: {}", err.path().display(), err.error());
+                    continue;
+                }
+            };
+            let Ok(relative) = src.strip_prefix(main_worktree_path) else {
+                continue;
+            };
+            if relative.components().next() == Some(Component::Normal(".git".as_ref())) {
+                continue;
+            }
+
+            let dest = worktree_path.join(relative);
+            if dest.symlink_metadata().is_ok() {
+                continue;
+            }
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+
+            transfer(&src, &dest, rule.mode)
+                .with_context(|| format!("Failed to {} {}", rule.mode, relative.display()))?;
+            eprintln!("{} {}", describe(rule.mode), relative.display());
+        }
+    }
+    Ok(())
+}
+
+fn describe(mode: CopyMode) -> &'static str {
+    match mode {
+        CopyMode::Copy => "Copied",
+        CopyMode::Symlink => "Symlinked",
+        CopyMode::Hardlink => "Hard-linked",
+        CopyMode::Reflink => "Cloned",
+    }
+}
+
+/// Put `src` at `dest` using `mode`, descending into directories for every
+/// mode but symlink, which links the directory itself
+fn transfer(src: &Path, dest: &Path, mode: CopyMode) -> Result<()> {
+    if mode == CopyMode::Symlink {
+        symlink(src, dest)?;
+        return Ok(());
+    }
+
+    let metadata = src.symlink_metadata()?;
+    if metadata.is_symlink() {
+        // Keep symlinks as symlinks rather than copying what they point to
+        symlink(&fs::read_link(src)?, dest)?;
+    } else if metadata.is_dir() {
+        fs::create_dir(dest)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            transfer(&entry.path(), &dest.join(entry.file_name()), mode)?;
+        }
+    } else {
+        match mode {
+            CopyMode::Hardlink => fs::hard_link(src, dest)?,
+            CopyMode::Reflink => {
+                reflink_copy::reflink_or_copy(src, dest)?;
+            }
+            CopyMode::Copy | CopyMode::Symlink => {
+                fs::copy(src, dest)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Create a symlink at `link` pointing to `target`
+#[cfg(unix)]
+pub fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symlink at `link` pointing to `target`. Windows links files and
+/// directories differently; a relative `target` is resolved from `link`'s directory.
+#[cfg(windows)]
+pub fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    let resolved = link.parent().unwrap_or(Path::new("")).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
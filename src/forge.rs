@@ -0,0 +1,404 @@
+//! Code hosting platforms and their pull requests.
+//!
+//! A [`Forge`] opens, finds, inspects and merges the pull request for a branch.
+//! GitHub goes through the `gh` CLI when it's installed and its REST API
+//! otherwise; GitLab and Gitea go through their REST APIs. The forge is picked
+//! from `forge.type` or the host of the `origin` remote, and `forge.api-url`
+//! points it at a self-hosted instance, or a mock server.
+
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use crate::config::Config;
+use crate::git;
+
+mod gitea;
+mod github;
+mod gitlab;
+#[cfg(test)]
+mod tests;
+
+/// Kinds of forge `forge.type` accepts
+pub const KINDS: &[&str] = &["github", "gitlab", "gitea", "none"];
+
+pub trait Forge {
+    /// The platform's name, for messages
+    fn name(&self) -> &'static str;
+
+    /// The most recent pull request from `branch`, whatever its state
+    fn pull_request(&self, branch: &str) -> Result<Option<PullRequest>>;
+
+    /// The pull request numbered `number`
+    fn pull_request_by_number(&self, number: u64) -> Result<PullRequest>;
+
+    /// The ref on the remote holding the head of pull request `number`,
+    /// including pull requests from forks
+    fn pull_request_ref(&self, number: u64) -> String {
+        format!("refs/pull/{}/head", number)
+    }
+
+    /// Open a pull request, requesting its reviewers and adding its labels
+    fn create_pull_request(&self, new: &NewPullRequest) -> Result<PullRequest>;
+
+    /// Merge `pr` with `strategy` (squash, merge or rebase), or with `auto`,
+    /// turn on auto-merge so the forge merges it once it's ready
+    fn merge(&self, pr: &PullRequest, strategy: &str, auto: bool) -> Result<()>;
+
+    /// Delete `branch` from the remote
+    fn delete_branch(&self, branch: &str) -> Result<()>;
+
+    /// The issue numbered `number`
+    fn issue(&self, number: u64) -> Result<Issue>;
+}
+
+/// An issue or ticket a worktree is for, from a forge or the ticket cache
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Issue {
+    /// The issue number, or a ticket key like `PROJ-12`
+    pub id: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum State {
+    Open,
+    Closed,
+    Merged,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Review {
+    Approved,
+    ChangesRequested,
+    /// An approving review is needed before it can merge
+    Required,
+    NotRequired,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    Passed,
+    Pending,
+    Failed,
+}
+
+/// A CI job or commit status reported on the pull request's head
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+}
+
+/// A pull request (merge request on GitLab) as every forge describes it
+pub struct PullRequest {
+    pub number: u64,
+    /// How the forge refers to it, e.g. "PR #12" or "MR !12"
+    pub reference: String,
+    pub title: String,
+    pub url: String,
+    /// The branch it comes from, possibly in a fork
+    pub head: String,
+    pub state: State,
+    pub draft: bool,
+    /// `None` while the forge is still working it out
+    pub mergeable: Option<bool>,
+    pub review: Review,
+    pub checks: Vec<Check>,
+}
+
+/// A pull request to open
+pub struct NewPullRequest {
+    pub branch: String,
+    pub base: String,
+    pub title: String,
+    pub body: String,
+    pub draft: bool,
+    /// Usernames to request reviews from
+    pub reviewers: Vec<String>,
+    pub labels: Vec<String>,
+}
+
+impl NewPullRequest {
+    /// Look up the pull request just opened from `new.branch`
+    fn created(&self, forge: &dyn Forge) -> Result<PullRequest> {
+        forge
+            .pull_request(&self.branch)?
+            .with_context(|| format!("Created a pull request from {}, but can't find it", self.branch))
+    }
+}
+
+/// Why a pull request can't be merged right now
+pub enum Blocker {
+    /// Needs someone to act before the PR can merge
+    Hard(String),
+    /// Clears up on its own, so auto-merge can wait for it
+    Waiting(String),
+}
+
+impl PullRequest {
+    pub fn is_merged(&self) -> bool {
+        self.state == State::Merged
+    }
+
+    fn checks_with(&self, status: CheckStatus) -> Vec<&str> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == status)
+            .map(|check| check.name.as_str())
+            .collect()
+    }
+
+    /// Everything standing in the way of merging, empty when it's ready
+    pub fn blockers(&self) -> Vec<Blocker> {
+        let mut blockers = Vec::new();
+        if self.state == State::Closed {
+            blockers.push(Blocker::Hard("it is closed".to_string()));
+        }
+        if self.draft {
+            blockers.push(Blocker::Hard("it is a draft".to_string()));
+        }
+        if self.mergeable == Some(false) {
+            blockers.push(Blocker::Hard("it conflicts with the base branch".to_string()));
+        }
+        match self.review {
+            Review::ChangesRequested => blockers.push(Blocker::Hard("changes were requested".to_string())),
+            Review::Required => blockers.push(Blocker::Waiting("it needs an approving review".to_string())),
+            Review::Approved | Review::NotRequired => {}
+        }
+        let failed = self.checks_with(CheckStatus::Failed);
+        if !failed.is_empty() {
+            blockers.push(Blocker::Hard(format!("checks failed: {}", failed.join(", "))));
+        }
+        let pending = self.checks_with(CheckStatus::Pending);
+        if !pending.is_empty() {
+            blockers.push(Blocker::Waiting(format!("checks are pending: {}", pending.join(", "))));
+        }
+        blockers
+    }
+
+    /// Print what the PR's checks, reviews and mergeability look like
+    pub fn print_summary(&self) {
+        let checks = if self.checks.is_empty() {
+            "none".to_string()
+        } else {
+            format!(
+                "{} passed, {} pending, {} failed",
+                self.checks_with(CheckStatus::Passed).len(),
+                self.checks_with(CheckStatus::Pending).len(),
+                self.checks_with(CheckStatus::Failed).len()
+            )
+        };
+        let state = match self.state {
+            State::Open => "open",
+            State::Closed => "closed",
+            State::Merged => "merged",
+        };
+        let review = match self.review {
+            Review::Approved => "approved",
+            Review::ChangesRequested => "changes requested",
+            Review::Required => "review required",
+            Review::NotRequired => "not required",
+        };
+        let mergeable = match self.mergeable {
+            Some(true) => "yes",
+            Some(false) => "no, it has conflicts",
+            None => "unknown",
+        };
+
+        eprintln!("{}: {}{}", self.reference, self.title, if self.draft { " (draft)" } else { "" });
+        eprintln!("  {}", self.url);
+        eprintln!("  State:     {}", state);
+        eprintln!("  Checks:    {}", checks);
+        eprintln!("  Review:    {}", review);
+        eprintln!("  Mergeable: {}", mergeable);
+    }
+}
+
+/// Where a repository lives on a forge, from its remote URL
+pub struct Remote {
+    pub host: String,
+    /// `owner/repo`, or `group/subgroup/repo` on GitLab
+    pub path: String,
+}
+
+impl Remote {
+    /// Parse `git@host:owner/repo.git`, `ssh://git@host:22/owner/repo` or
+    /// `https://host/owner/repo.git`. Local paths have no host and give `None`.
+    pub fn parse(url: &str) -> Option<Remote> {
+        let (host, path) = match url.split_once("://") {
+            Some((_, rest)) => rest.split_once('/')?,
+            None => url.split_once(':')?,
+        };
+        // Drop any user and port
+        let host = host.rsplit('@').next()?.split(':').next()?;
+        let path = path.trim_matches('/').trim_end_matches(".git");
+        if host.is_empty() || !path.contains('/') {
+            return None;
+        }
+        Some(Remote {
+            host: host.to_lowercase(),
+            path: path.to_string(),
+        })
+    }
+
+    /// The owner and repository name
+    pub fn owner_and_name(&self) -> (&str, &str) {
+        self.path.rsplit_once('/').unwrap_or(("", &self.path))
+    }
+}
+
+/// The forge hosting the `origin` remote, `None` if there isn't one to talk to.
+/// `dir` is where CLI-based forges run.
+pub fn detect(config: &Config, dir: &Path) -> Result<Option<Box<dyn Forge>>> {
+    let remote = git::backend().remote_url("origin")?.as_deref().and_then(Remote::parse);
+
+    let kind = match (config.forge.as_deref(), &remote) {
+        (Some(kind), _) => kind,
+        (None, None) => return Ok(None),
+        (None, Some(remote)) if remote.host.contains("github") => "github",
+        (None, Some(remote)) if remote.host.contains("gitlab") => "gitlab",
+        (None, Some(remote)) if ["gitea", "forgejo", "codeberg"].iter().any(|name| remote.host.contains(name)) => "gitea",
+        (None, Some(_)) => return Ok(None),
+    };
+    if kind == "none" {
+        return Ok(None);
+    }
+    let Some(remote) = remote else {
+        bail!("forge.type is {} but the origin remote's URL doesn't name a host and repository", kind);
+    };
+
+    let api_url = config.forge_api_url.as_deref();
+    let forge: Box<dyn Forge> = match kind {
+        "github" => github::connect(remote, api_url, dir),
+        "gitlab" => Box::new(gitlab::GitLab::new(remote, api_url)),
+        "gitea" => Box::new(gitea::Gitea::new(remote, api_url)),
+        _ => bail!("Unknown forge.type: {}. Use {}", kind, KINDS.join(", ")),
+    };
+    Ok(Some(forge))
+}
+
+/// JSON over HTTP, for the forges' REST APIs
+struct Api {
+    agent: ureq::Agent,
+    base: String,
+    /// Header carrying the access token, if one is set
+    auth: Option<(&'static str, String)>,
+}
+
+impl Api {
+    fn new(base: &str, auth: Option<(&'static str, String)>) -> Api {
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .timeout_global(Some(Duration::from_secs(30)))
+            .build()
+            .into();
+        Api {
+            agent,
+            base: base.trim_end_matches('/').to_string(),
+            auth,
+        }
+    }
+
+    /// Send a request to `path` under the API's base URL. Returns the status and the body.
+    fn request(&self, method: &str, path: &str, body: Option<&Value>) -> Result<(u16, String)> {
+        let url = format!("{}{}", self.base, path);
+        let mut request = ureq::http::Request::builder()
+            .method(method)
+            .uri(&url)
+            .header("Accept", "application/json")
+            .header("User-Agent", "worktree");
+        if let Some((header, token)) = &self.auth {
+            request = request.header(*header, token);
+        }
+        if body.is_some() {
+            request = request.header("Content-Type", "application/json");
+        }
+        let body = body.map(Value::to_string).unwrap_or_default();
+        let request = request.body(body).with_context(|| format!("Invalid request URL: {}", url))?;
+
+        let mut response = self
+            .agent
+            .run(request)
+            .with_context(|| format!("{} {} failed", method, url))?;
+        let status = response.status().as_u16();
+        let text = response
+            .body_mut()
+            .read_to_string()
+            .with_context(|| format!("Failed to read the response to {} {}", method, url))?;
+        Ok((status, text))
+    }
+
+    /// Send a request and fail unless it succeeded
+    fn send(&self, method: &str, path: &str, body: Option<&Value>) -> Result<String> {
+        let (status, text) = self.request(method, path, body)?;
+        if !(200..300).contains(&status) {
+            bail!("{} {}{} returned {}: {}", method, self.base, path, status, error_message(&text));
+        }
+        Ok(text)
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let text = self.send("GET", path, None)?;
+        serde_json::from_str(&text).with_context(|| format!("Unexpected response from {}{}", self.base, path))
+    }
+
+    /// Every item of the list at `path`, fetched `per_page` at a time. The
+    /// forges name the page size differently, so `size_param` gives its name.
+    fn get_all<T: DeserializeOwned>(&self, path: &str, size_param: &str, per_page: usize) -> Result<Vec<T>> {
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let mut items = Vec::new();
+        for page in 1.. {
+            let batch: Vec<T> = self.get(&format!("{}{}{}={}&page={}", path, separator, size_param, per_page, page))?;
+            let last_page = batch.len() < per_page;
+            items.extend(batch);
+            if last_page {
+                break;
+            }
+        }
+        Ok(items)
+    }
+}
+
+/// The overall review from each reviewer's latest review, given oldest first.
+/// Any outstanding request for changes wins over approvals.
+fn review_decision<'a>(reviews: impl IntoIterator<Item = (&'a str, Review)>) -> Review {
+    let latest: BTreeMap<&str, Review> = reviews.into_iter().collect();
+    if latest.values().any(|review| *review == Review::ChangesRequested) {
+        Review::ChangesRequested
+    } else if latest.values().any(|review| *review == Review::Approved) {
+        Review::Approved
+    } else {
+        Review::NotRequired
+    }
+}
+
+/// The message in an API error body, or the body itself
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|json| json.get("message").map(|message| message.to_string().trim_matches('"').to_string()))
+        .unwrap_or_else(|| body.trim().to_string())
+}
+
+/// Percent-encode `text` for use as a single URL path segment or query value
+fn encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// The first of `names` set in the environment
+fn token(names: &[&str]) -> Option<String> {
+    names.iter().find_map(|name| std::env::var(name).ok().filter(|token| !token.is_empty()))
+}
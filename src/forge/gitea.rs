@@ -0,0 +1,267 @@
+//! Gitea and Forgejo pull requests, through the REST API.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use super::{
+    encode, review_decision, token, Api, Check, CheckStatus, Forge, Issue, NewPullRequest, PullRequest, Remote, Review,
+    State,
+};
+
+/// Items fetched per page, the most Gitea returns by default
+const PAGE_SIZE: usize = 50;
+
+pub struct Gitea {
+    api: Api,
+    remote: Remote,
+}
+
+#[derive(Deserialize)]
+struct Pull {
+    number: u64,
+    title: String,
+    html_url: String,
+    /// open or closed, including merged
+    state: String,
+    #[serde(default)]
+    merged: bool,
+    #[serde(default)]
+    draft: bool,
+    /// Absent or null until Gitea has checked for conflicts
+    #[serde(default)]
+    mergeable: Option<bool>,
+    head: Head,
+}
+
+#[derive(Deserialize)]
+struct Head {
+    #[serde(rename = "ref")]
+    branch: String,
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct PullReview {
+    /// APPROVED, REQUEST_CHANGES, COMMENT, PENDING or REQUEST_REVIEW
+    state: String,
+    #[serde(default)]
+    dismissed: bool,
+    user: Option<User>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct Label {
+    id: u64,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RepoIssue {
+    title: String,
+    html_url: String,
+}
+
+#[derive(Deserialize)]
+struct Created {
+    number: u64,
+}
+
+#[derive(Deserialize)]
+struct CombinedStatus {
+    statuses: Option<Vec<CommitStatus>>,
+}
+
+#[derive(Deserialize)]
+struct CommitStatus {
+    context: String,
+    /// pending, success, error, failure or warning
+    status: String,
+}
+
+impl Gitea {
+    pub fn new(remote: Remote, api_url: Option<&str>) -> Gitea {
+        let base = match api_url {
+            Some(url) => url.to_string(),
+            None => format!("https://{}/api/v1", remote.host),
+        };
+        let auth = token(&["GITEA_TOKEN", "FORGEJO_TOKEN"]).map(|token| ("Authorization", format!("token {}", token)));
+        Gitea {
+            api: Api::new(&base, auth),
+            remote,
+        }
+    }
+
+    /// The most recently updated pull request from `branch`. Gitea can't
+    /// filter by head branch, so this pages through all of them.
+    fn find(&self, branch: &str) -> Result<Option<Pull>> {
+        for page in 1.. {
+            let pulls: Vec<Pull> = self.api.get(&format!(
+                "/repos/{}/pulls?state=all&limit={}&page={}",
+                self.remote.path, PAGE_SIZE, page
+            ))?;
+            let last_page = pulls.len() < PAGE_SIZE;
+            if let Some(pull) = pulls.into_iter().find(|pull| pull.head.branch == branch) {
+                return Ok(Some(pull));
+            }
+            if last_page {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Fill in `pull`'s reviews and checks
+    fn describe(&self, pull: Pull) -> Result<PullRequest> {
+        let reviews: Vec<PullReview> = self.api.get_all(
+            &format!("/repos/{}/pulls/{}/reviews", self.remote.path, pull.number),
+            "limit",
+            PAGE_SIZE,
+        )?;
+        let review = review_decision(reviews.iter().filter(|review| !review.dismissed).filter_map(|review| {
+            let user = review.user.as_ref()?.login.as_str();
+            match review.state.as_str() {
+                "APPROVED" => Some((user, Review::Approved)),
+                "REQUEST_CHANGES" => Some((user, Review::ChangesRequested)),
+                _ => None,
+            }
+        }));
+
+        let status: CombinedStatus = self
+            .api
+            .get(&format!("/repos/{}/commits/{}/status", self.remote.path, pull.head.sha))?;
+        let checks = status
+            .statuses
+            .unwrap_or_default()
+            .into_iter()
+            .map(|status| Check {
+                status: match status.status.as_str() {
+                    "success" | "warning" => CheckStatus::Passed,
+                    "pending" => CheckStatus::Pending,
+                    _ => CheckStatus::Failed,
+                },
+                name: status.context,
+            })
+            .collect();
+
+        let state = match (pull.state.as_str(), pull.merged) {
+            (_, true) => State::Merged,
+            ("closed", false) => State::Closed,
+            _ => State::Open,
+        };
+        // Older versions only mark drafts by a title prefix
+        let draft = pull.draft || ["WIP:", "[WIP]"].iter().any(|prefix| pull.title.starts_with(prefix));
+        Ok(PullRequest {
+            number: pull.number,
+            reference: format!("PR #{}", pull.number),
+            title: pull.title,
+            url: pull.html_url,
+            head: pull.head.branch,
+            mergeable: pull.mergeable.filter(|_| state == State::Open),
+            state,
+            draft,
+            review,
+            checks,
+        })
+    }
+
+    /// Gitea adds labels by ID rather than name
+    fn label_ids(&self, names: &[String]) -> Result<Vec<u64>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let labels: Vec<Label> = self
+            .api
+            .get_all(&format!("/repos/{}/labels", self.remote.path), "limit", PAGE_SIZE)?;
+        names
+            .iter()
+            .map(|name| match labels.iter().find(|label| label.name == *name) {
+                Some(label) => Ok(label.id),
+                None => bail!("No label named {} in {}", name, self.remote.path),
+            })
+            .collect()
+    }
+}
+
+impl Forge for Gitea {
+    fn name(&self) -> &'static str {
+        "Gitea"
+    }
+
+    fn pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        match self.find(branch)? {
+            Some(pull) => self.describe(pull).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn pull_request_by_number(&self, number: u64) -> Result<PullRequest> {
+        let pull: Pull = self
+            .api
+            .get(&format!("/repos/{}/pulls/{}", self.remote.path, number))?;
+        self.describe(pull)
+    }
+
+    fn create_pull_request(&self, new: &NewPullRequest) -> Result<PullRequest> {
+        // Drafts are marked by their title
+        let title = match new.draft {
+            true => format!("WIP: {}", new.title),
+            false => new.title.clone(),
+        };
+        let body = json!({
+            "head": new.branch,
+            "base": new.base,
+            "title": title,
+            "body": new.body,
+            "labels": self.label_ids(&new.labels)?,
+        });
+        let response = self
+            .api
+            .send("POST", &format!("/repos/{}/pulls", self.remote.path), Some(&body))
+            .context("Failed to create the PR")?;
+        let created: Created = serde_json::from_str(&response).context("Unexpected response creating the PR")?;
+
+        if !new.reviewers.is_empty() {
+            let path = format!("/repos/{}/pulls/{}/requested_reviewers", self.remote.path, created.number);
+            self.api
+                .send("POST", &path, Some(&json!({ "reviewers": new.reviewers })))
+                .context("Failed to request reviews")?;
+        }
+        new.created(self)
+    }
+
+    fn merge(&self, pr: &PullRequest, strategy: &str, auto: bool) -> Result<()> {
+        if auto {
+            eprintln!("Setting {} to merge when its checks succeed", pr.reference);
+        } else {
+            eprintln!("Merging {} through the Gitea API", pr.reference);
+        }
+        let body = json!({
+            "Do": strategy,
+            "merge_when_checks_succeed": auto,
+        });
+        self.api
+            .send("POST", &format!("/repos/{}/pulls/{}/merge", self.remote.path, pr.number), Some(&body))
+            .with_context(|| format!("Failed to merge {}", pr.reference))?;
+        Ok(())
+    }
+
+    fn delete_branch(&self, branch: &str) -> Result<()> {
+        self.api
+            .send("DELETE", &format!("/repos/{}/branches/{}", self.remote.path, encode(branch)), None)?;
+        Ok(())
+    }
+
+    fn issue(&self, number: u64) -> Result<Issue> {
+        let issue: RepoIssue = self.api.get(&format!("/repos/{}/issues/{}", self.remote.path, number))?;
+        Ok(Issue {
+            id: number.to_string(),
+            title: issue.title,
+            url: Some(issue.html_url),
+        })
+    }
+}
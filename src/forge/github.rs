@@ -0,0 +1,472 @@
+//! GitHub, through the `gh` CLI or the REST API.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use super::{
+    encode, review_decision, token, Api, Check, CheckStatus, Forge, Issue, NewPullRequest, PullRequest, Remote, Review,
+    State,
+};
+
+/// `gh` when it's installed and no API URL is configured, since it already
+/// has the user's credentials; the REST API otherwise
+pub fn connect(remote: Remote, api_url: Option<&str>, dir: &Path) -> Box<dyn Forge> {
+    let has_gh = Command::new("gh").arg("--version").output().is_ok_and(|output| output.status.success());
+    if api_url.is_none() && has_gh {
+        return Box::new(GhCli {
+            remote,
+            dir: dir.to_path_buf(),
+        });
+    }
+
+    let base = match api_url {
+        Some(url) => url.to_string(),
+        None if remote.host == "github.com" => "https://api.github.com".to_string(),
+        None => format!("https://{}/api/v3", remote.host),
+    };
+    // GitHub Enterprise serves GraphQL from /api/graphql rather than /api/v3/graphql
+    let graphql = match base.trim_end_matches('/').strip_suffix("/api/v3") {
+        Some(host) => format!("{}/api/graphql", host),
+        None => format!("{}/graphql", base.trim_end_matches('/')),
+    };
+    let auth = token(&["GITHUB_TOKEN", "GH_TOKEN"]).map(|token| ("Authorization", format!("Bearer {}", token)));
+    Box::new(GitHubApi {
+        api: Api::new(&base, auth.clone()),
+        graphql: Api::new(&graphql, auth),
+        remote,
+    })
+}
+
+fn check_status(status: Option<&str>, result: Option<&str>) -> CheckStatus {
+    match result.unwrap_or_default() {
+        _ if status.is_some_and(|status| status != "COMPLETED" && status != "completed") => CheckStatus::Pending,
+        "SUCCESS" | "NEUTRAL" | "SKIPPED" | "success" | "neutral" | "skipped" => CheckStatus::Passed,
+        "" | "PENDING" | "EXPECTED" | "pending" => CheckStatus::Pending,
+        _ => CheckStatus::Failed,
+    }
+}
+
+/// A branch's ref path, with each component encoded but the slashes kept
+fn ref_path(branch: &str) -> String {
+    branch.split('/').map(encode).collect::<Vec<_>>().join("/")
+}
+
+struct GhCli {
+    remote: Remote,
+    dir: PathBuf,
+}
+
+/// What `gh pr view --json` reports
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GhPullRequest {
+    number: u64,
+    title: String,
+    url: String,
+    /// OPEN, CLOSED or MERGED
+    state: String,
+    is_draft: bool,
+    head_ref_name: String,
+    /// MERGEABLE, CONFLICTING or UNKNOWN
+    #[serde(default)]
+    mergeable: Option<String>,
+    /// APPROVED, CHANGES_REQUESTED or REVIEW_REQUIRED; empty when no review is needed
+    #[serde(default)]
+    review_decision: Option<String>,
+    #[serde(default)]
+    status_check_rollup: Option<Vec<GhCheck>>,
+}
+
+/// A check run (from Actions and other apps) or a commit status
+#[derive(Deserialize)]
+struct GhCheck {
+    name: Option<String>,
+    context: Option<String>,
+    /// Check runs: QUEUED, IN_PROGRESS, COMPLETED, ...
+    status: Option<String>,
+    /// Check runs: SUCCESS, FAILURE, NEUTRAL, SKIPPED, CANCELLED, ...
+    conclusion: Option<String>,
+    /// Commit statuses: SUCCESS, PENDING, FAILURE, ERROR, EXPECTED
+    state: Option<String>,
+}
+
+/// The fields `gh pr view` and `gh pr list` are asked for
+const GH_FIELDS: &str = "number,title,url,headRefName,state,isDraft,mergeable,reviewDecision,statusCheckRollup";
+
+impl From<GhPullRequest> for PullRequest {
+    fn from(pr: GhPullRequest) -> Self {
+        PullRequest {
+            number: pr.number,
+            reference: format!("PR #{}", pr.number),
+            title: pr.title,
+            url: pr.url,
+            head: pr.head_ref_name,
+            state: match pr.state.as_str() {
+                "MERGED" => State::Merged,
+                "CLOSED" => State::Closed,
+                _ => State::Open,
+            },
+            draft: pr.is_draft,
+            mergeable: match pr.mergeable.as_deref() {
+                Some("MERGEABLE") => Some(true),
+                Some("CONFLICTING") => Some(false),
+                _ => None,
+            },
+            review: match pr.review_decision.as_deref() {
+                Some("APPROVED") => Review::Approved,
+                Some("CHANGES_REQUESTED") => Review::ChangesRequested,
+                Some("REVIEW_REQUIRED") => Review::Required,
+                _ => Review::NotRequired,
+            },
+            checks: pr
+                .status_check_rollup
+                .unwrap_or_default()
+                .into_iter()
+                .map(|check| Check {
+                    status: check_status(
+                        check.status.as_deref(),
+                        check.conclusion.as_deref().or(check.state.as_deref()),
+                    ),
+                    name: check.name.or(check.context).unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl GhCli {
+    /// Run `gh` and parse the JSON it prints, failing with its stderr
+    fn gh_json<T: serde::de::DeserializeOwned>(&self, args: &[&str]) -> Result<T> {
+        let output = self.gh(args)?;
+        if !output.status.success() {
+            bail!("gh {} failed: {}", args[..2].join(" "), String::from_utf8_lossy(&output.stderr).trim());
+        }
+        serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("Failed to parse gh {} output", args[..2].join(" ")))
+    }
+
+    fn gh(&self, args: &[&str]) -> Result<std::process::Output> {
+        Command::new("gh")
+            .current_dir(&self.dir)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to execute gh {}. Is the GitHub CLI installed?", args[..2].join(" ")))
+    }
+}
+
+impl Forge for GhCli {
+    fn name(&self) -> &'static str {
+        "GitHub"
+    }
+
+    fn pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        // An empty list says there's no PR, where `gh pr view` only fails with a message.
+        // Like `gh pr view`, prefer an open PR to the latest closed or merged one.
+        let prs: Vec<GhPullRequest> = self.gh_json(&[
+            "pr", "list", "--head", branch, "--state", "all", "--limit", "20", "--json", GH_FIELDS,
+        ])?;
+        let open = prs.iter().position(|pr| pr.state == "OPEN").unwrap_or(0);
+        Ok(prs.into_iter().nth(open).map(PullRequest::from))
+    }
+
+    fn pull_request_by_number(&self, number: u64) -> Result<PullRequest> {
+        let pr: GhPullRequest = self
+            .gh_json(&["pr", "view", &number.to_string(), "--json", GH_FIELDS])
+            .with_context(|| format!("Failed to look up PR #{} in {}", number, self.remote.path))?;
+        Ok(pr.into())
+    }
+
+    fn create_pull_request(&self, new: &NewPullRequest) -> Result<PullRequest> {
+        let mut args = vec![
+            "pr", "create", "--head", &new.branch, "--base", &new.base, "--title", &new.title, "--body", &new.body,
+        ];
+        if new.draft {
+            args.push("--draft");
+        }
+        for reviewer in &new.reviewers {
+            args.extend(["--reviewer", reviewer]);
+        }
+        for label in &new.labels {
+            args.extend(["--label", label]);
+        }
+        let output = self.gh(&args)?;
+        if !output.status.success() {
+            bail!("Failed to create the PR: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        new.created(self)
+    }
+
+    fn merge(&self, pr: &PullRequest, strategy: &str, auto: bool) -> Result<()> {
+        let number = pr.number.to_string();
+        let strategy_flag = format!("--{}", strategy);
+        let mut args = vec!["pr", "merge", &number, &strategy_flag];
+        if auto {
+            args.push("--auto");
+        }
+        eprintln!("Running: gh {}", args.join(" "));
+        let output = self.gh(&args)?;
+
+        // Print gh output
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stdout.is_empty() {
+            eprintln!("{}", stdout);
+        }
+        if !output.status.success() {
+            bail!("Failed to merge PR: {}", stderr);
+        }
+        if !stderr.is_empty() {
+            eprintln!("{}", stderr);
+        }
+        Ok(())
+    }
+
+    fn delete_branch(&self, branch: &str) -> Result<()> {
+        let endpoint = format!("repos/{}/git/refs/heads/{}", self.remote.path, ref_path(branch));
+        let output = self.gh(&["api", "--method", "DELETE", &endpoint])?;
+        if !output.status.success() {
+            bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+
+    fn issue(&self, number: u64) -> Result<Issue> {
+        let output = self.gh(&["issue", "view", &number.to_string(), "--json", "title,url"])?;
+        if !output.status.success() {
+            bail!("Failed to look up issue #{}: {}", number, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        let issue: ApiIssue = serde_json::from_slice(&output.stdout).context("Failed to parse gh issue view output")?;
+        Ok(Issue {
+            id: number.to_string(),
+            title: issue.title,
+            url: Some(issue.url),
+        })
+    }
+}
+
+struct GitHubApi {
+    api: Api,
+    graphql: Api,
+    remote: Remote,
+}
+
+#[derive(Deserialize)]
+struct ApiPull {
+    number: u64,
+    node_id: String,
+    title: String,
+    html_url: String,
+    /// open or closed, including merged
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    merged_at: Option<String>,
+    /// Only in single pull requests; null while GitHub works it out
+    #[serde(default)]
+    mergeable: Option<bool>,
+    head: ApiHead,
+}
+
+/// An issue as `gh issue view` and the REST API report it
+#[derive(Deserialize)]
+struct ApiIssue {
+    title: String,
+    #[serde(alias = "html_url")]
+    url: String,
+}
+
+/// Just enough of a pull request or issue to refer to it
+#[derive(Deserialize)]
+struct ApiNumber {
+    number: u64,
+}
+
+#[derive(Deserialize)]
+struct ApiHead {
+    #[serde(rename = "ref")]
+    branch: String,
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct ApiReview {
+    state: String,
+    user: Option<ApiUser>,
+}
+
+#[derive(Deserialize)]
+struct ApiUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct ApiCheckRuns {
+    check_runs: Vec<ApiCheckRun>,
+}
+
+#[derive(Deserialize)]
+struct ApiCheckRun {
+    name: String,
+    status: String,
+    conclusion: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ApiCombinedStatus {
+    statuses: Vec<ApiStatus>,
+}
+
+#[derive(Deserialize)]
+struct ApiStatus {
+    context: String,
+    state: String,
+}
+
+impl GitHubApi {
+    fn pull(&self, number: u64) -> Result<ApiPull> {
+        self.api.get(&format!("/repos/{}/pulls/{}", self.remote.path, number))
+    }
+}
+
+impl Forge for GitHubApi {
+    fn name(&self) -> &'static str {
+        "GitHub"
+    }
+
+    fn pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let (owner, _) = self.remote.owner_and_name();
+        let head = encode(&format!("{}:{}", owner, branch));
+        let pulls: Vec<ApiPull> = self
+            .api
+            .get(&format!("/repos/{}/pulls?head={}&state=all&per_page=1", self.remote.path, head))?;
+        match pulls.first() {
+            Some(pull) => self.pull_request_by_number(pull.number).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn pull_request_by_number(&self, number: u64) -> Result<PullRequest> {
+        let pull = self.pull(number)?;
+
+        let reviews: Vec<ApiReview> = self.api.get_all(
+            &format!("/repos/{}/pulls/{}/reviews", self.remote.path, pull.number),
+            "per_page",
+            100,
+        )?;
+        let review = review_decision(reviews.iter().filter_map(|review| {
+            let user = review.user.as_ref()?.login.as_str();
+            match review.state.as_str() {
+                "APPROVED" => Some((user, Review::Approved)),
+                "CHANGES_REQUESTED" => Some((user, Review::ChangesRequested)),
+                "DISMISSED" => Some((user, Review::NotRequired)),
+                _ => None,
+            }
+        }));
+
+        let runs: ApiCheckRuns = self
+            .api
+            .get(&format!("/repos/{}/commits/{}/check-runs?per_page=100", self.remote.path, pull.head.sha))?;
+        let statuses: ApiCombinedStatus = self
+            .api
+            .get(&format!("/repos/{}/commits/{}/status", self.remote.path, pull.head.sha))?;
+        let checks = runs
+            .check_runs
+            .into_iter()
+            .map(|run| Check {
+                status: check_status(Some(&run.status), run.conclusion.as_deref()),
+                name: run.name,
+            })
+            .chain(statuses.statuses.into_iter().map(|status| Check {
+                status: check_status(None, Some(&status.state)),
+                name: status.context,
+            }))
+            .collect();
+
+        Ok(PullRequest {
+            number: pull.number,
+            reference: format!("PR #{}", pull.number),
+            title: pull.title,
+            url: pull.html_url,
+            head: pull.head.branch,
+            state: match (pull.state.as_str(), pull.merged_at) {
+                (_, Some(_)) => State::Merged,
+                ("closed", None) => State::Closed,
+                _ => State::Open,
+            },
+            draft: pull.draft,
+            mergeable: pull.mergeable,
+            review,
+            checks,
+        })
+    }
+
+    fn create_pull_request(&self, new: &NewPullRequest) -> Result<PullRequest> {
+        let body = json!({
+            "head": new.branch,
+            "base": new.base,
+            "title": new.title,
+            "body": new.body,
+            "draft": new.draft,
+        });
+        let response = self
+            .api
+            .send("POST", &format!("/repos/{}/pulls", self.remote.path), Some(&body))
+            .context("Failed to create the PR")?;
+        let created: ApiNumber = serde_json::from_str(&response).context("Unexpected response creating the PR")?;
+
+        if !new.reviewers.is_empty() {
+            let path = format!("/repos/{}/pulls/{}/requested_reviewers", self.remote.path, created.number);
+            self.api
+                .send("POST", &path, Some(&json!({ "reviewers": new.reviewers })))
+                .context("Failed to request reviews")?;
+        }
+        if !new.labels.is_empty() {
+            // Pull requests share their numbers and labels with issues
+            let path = format!("/repos/{}/issues/{}/labels", self.remote.path, created.number);
+            self.api
+                .send("POST", &path, Some(&json!({ "labels": new.labels })))
+                .context("Failed to add labels")?;
+        }
+        new.created(self)
+    }
+
+    fn merge(&self, pr: &PullRequest, strategy: &str, auto: bool) -> Result<()> {
+        if !auto {
+            eprintln!("Merging {} through the GitHub API", pr.reference);
+            let path = format!("/repos/{}/pulls/{}/merge", self.remote.path, pr.number);
+            self.api
+                .send("PUT", &path, Some(&json!({ "merge_method": strategy })))
+                .with_context(|| format!("Failed to merge {}", pr.reference))?;
+            return Ok(());
+        }
+
+        // Auto-merge is only exposed through GraphQL
+        eprintln!("Enabling auto-merge for {} through the GitHub API", pr.reference);
+        let query = "mutation($id: ID!, $method: PullRequestMergeMethod!) { \
+            enablePullRequestAutoMerge(input: {pullRequestId: $id, mergeMethod: $method}) { clientMutationId } }";
+        let variables = json!({ "id": self.pull(pr.number)?.node_id, "method": strategy.to_uppercase() });
+        let response = self.graphql.send("POST", "", Some(&json!({ "query": query, "variables": variables })))?;
+        let response: serde_json::Value = serde_json::from_str(&response).context("Unexpected GraphQL response")?;
+        if let Some(errors) = response.get("errors") {
+            bail!("Failed to enable auto-merge for {}: {}", pr.reference, errors);
+        }
+        Ok(())
+    }
+
+    fn delete_branch(&self, branch: &str) -> Result<()> {
+        self.api
+            .send("DELETE", &format!("/repos/{}/git/refs/heads/{}", self.remote.path, ref_path(branch)), None)?;
+        Ok(())
+    }
+
+    fn issue(&self, number: u64) -> Result<Issue> {
+        let issue: ApiIssue = self.api.get(&format!("/repos/{}/issues/{}", self.remote.path, number))?;
+        Ok(Issue {
+            id: number.to_string(),
+            title: issue.title,
+            url: Some(issue.url),
+        })
+    }
+}
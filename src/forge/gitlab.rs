@@ -0,0 +1,233 @@
+//! GitLab merge requests, through the REST API.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+use super::{encode, token, Api, Check, CheckStatus, Forge, Issue, NewPullRequest, PullRequest, Remote, Review, State};
+
+pub struct GitLab {
+    api: Api,
+    /// The project's path, encoded as its ID
+    project: String,
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    iid: u64,
+    title: String,
+    web_url: String,
+    source_branch: String,
+    /// opened, closed, locked or merged
+    state: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    has_conflicts: bool,
+    /// mergeable, checking, ci_still_running, requested_changes, ...
+    detailed_merge_status: Option<String>,
+    head_pipeline: Option<Pipeline>,
+}
+
+#[derive(Deserialize)]
+struct ProjectIssue {
+    title: String,
+    web_url: String,
+}
+
+#[derive(Deserialize)]
+struct Project {
+    /// merge, rebase_merge or ff
+    merge_method: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct Pipeline {
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct Approvals {
+    #[serde(default)]
+    approvals_left: u64,
+    #[serde(default)]
+    approved_by: Vec<serde_json::Value>,
+}
+
+impl GitLab {
+    pub fn new(remote: Remote, api_url: Option<&str>) -> GitLab {
+        let base = match api_url {
+            Some(url) => url.to_string(),
+            None => format!("https://{}/api/v4", remote.host),
+        };
+        let auth = token(&["GITLAB_TOKEN"]).map(|token| ("PRIVATE-TOKEN", token));
+        GitLab {
+            api: Api::new(&base, auth),
+            project: encode(&remote.path),
+        }
+    }
+
+    fn merge_request_path(&self, iid: u64) -> String {
+        format!("/projects/{}/merge_requests/{}", self.project, iid)
+    }
+
+    /// How the project lands merge requests, if we may see its settings
+    fn merge_method(&self) -> Option<String> {
+        let project: Project = self.api.get(&format!("/projects/{}", self.project)).ok()?;
+        project.merge_method
+    }
+
+    /// GitLab assigns reviewers by ID rather than username
+    fn user_id(&self, username: &str) -> Result<u64> {
+        let users: Vec<User> = self.api.get(&format!("/users?username={}", encode(username)))?;
+        match users.first() {
+            Some(user) => Ok(user.id),
+            None => bail!("No GitLab user named {}", username),
+        }
+    }
+}
+
+impl Forge for GitLab {
+    fn name(&self) -> &'static str {
+        "GitLab"
+    }
+
+    fn pull_request(&self, branch: &str) -> Result<Option<PullRequest>> {
+        let found: Vec<MergeRequest> = self.api.get(&format!(
+            "/projects/{}/merge_requests?source_branch={}&state=all&order_by=created_at&sort=desc&per_page=1",
+            self.project,
+            encode(branch)
+        ))?;
+        match found.first() {
+            // Lists leave out the pipeline and merge status
+            Some(found) => self.pull_request_by_number(found.iid).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn pull_request_by_number(&self, number: u64) -> Result<PullRequest> {
+        let mr: MergeRequest = self.api.get(&self.merge_request_path(number))?;
+
+        // Approval rules are a paid feature; without them no approval is required
+        let approvals = self
+            .api
+            .get::<Approvals>(&format!("{}/approvals", self.merge_request_path(mr.iid)))
+            .ok();
+        let review = match (&approvals, mr.detailed_merge_status.as_deref()) {
+            (_, Some("requested_changes")) => Review::ChangesRequested,
+            (Some(approvals), _) if approvals.approvals_left > 0 => Review::Required,
+            (Some(approvals), _) if !approvals.approved_by.is_empty() => Review::Approved,
+            _ => Review::NotRequired,
+        };
+
+        let checks = mr
+            .head_pipeline
+            .iter()
+            .map(|pipeline| Check {
+                name: "pipeline".to_string(),
+                status: match pipeline.status.as_str() {
+                    "success" | "skipped" | "manual" => CheckStatus::Passed,
+                    "failed" | "canceled" => CheckStatus::Failed,
+                    _ => CheckStatus::Pending,
+                },
+            })
+            .collect();
+
+        Ok(PullRequest {
+            number: mr.iid,
+            reference: format!("MR !{}", mr.iid),
+            title: mr.title,
+            url: mr.web_url,
+            head: mr.source_branch,
+            state: match mr.state.as_str() {
+                "merged" => State::Merged,
+                "opened" => State::Open,
+                _ => State::Closed,
+            },
+            draft: mr.draft,
+            mergeable: match mr.detailed_merge_status.as_deref() {
+                _ if mr.has_conflicts => Some(false),
+                Some("checking" | "unchecked" | "approvals_syncing") | None => None,
+                Some(_) => Some(true),
+            },
+            review,
+            checks,
+        })
+    }
+
+    fn pull_request_ref(&self, number: u64) -> String {
+        format!("refs/merge-requests/{}/head", number)
+    }
+
+    fn create_pull_request(&self, new: &NewPullRequest) -> Result<PullRequest> {
+        let reviewer_ids = new
+            .reviewers
+            .iter()
+            .map(|reviewer| self.user_id(reviewer))
+            .collect::<Result<Vec<_>>>()?;
+        let title = match new.draft {
+            true => format!("Draft: {}", new.title),
+            false => new.title.clone(),
+        };
+        let body = json!({
+            "source_branch": new.branch,
+            "target_branch": new.base,
+            "title": title,
+            "description": new.body,
+            "labels": new.labels.join(","),
+            "reviewer_ids": reviewer_ids,
+        });
+        self.api
+            .send("POST", &format!("/projects/{}/merge_requests", self.project), Some(&body))
+            .context("Failed to create the merge request")?;
+        new.created(self)
+    }
+
+    fn merge(&self, pr: &PullRequest, strategy: &str, auto: bool) -> Result<()> {
+        // Squashing is per merge request; whether to rebase or merge is the project's setting
+        let honoured = strategy == "squash"
+            || match self.merge_method() {
+                Some(method) if strategy == "merge" => method == "merge",
+                Some(method) => method != "merge",
+                None => true,
+            };
+        if !honoured {
+            eprintln!("GitLab can't {} {}; it lands with the project's merge method", strategy, pr.reference);
+        }
+        if auto {
+            eprintln!("Setting {} to merge when its pipeline succeeds", pr.reference);
+        } else {
+            eprintln!("Merging {} through the GitLab API", pr.reference);
+        }
+        let body = json!({
+            "squash": strategy == "squash",
+            "merge_when_pipeline_succeeds": auto,
+        });
+        self.api
+            .send("PUT", &format!("{}/merge", self.merge_request_path(pr.number)), Some(&body))
+            .with_context(|| format!("Failed to merge {}", pr.reference))?;
+        Ok(())
+    }
+
+    fn delete_branch(&self, branch: &str) -> Result<()> {
+        self.api.send(
+            "DELETE",
+            &format!("/projects/{}/repository/branches/{}", self.project, encode(branch)),
+            None,
+        )?;
+        Ok(())
+    }
+
+    fn issue(&self, number: u64) -> Result<Issue> {
+        let issue: ProjectIssue = self.api.get(&format!("/projects/{}/issues/{}", self.project, number))?;
+        Ok(Issue {
+            id: number.to_string(),
+            title: issue.title,
+            url: Some(issue.web_url),
+        })
+    }
+}
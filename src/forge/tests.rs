@@ -0,0 +1,482 @@
+//! Remote URL parsing, review decisions, and each forge's REST client talking
+//! to a mock server through its API URL, the way `forge.api-url` points it.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use super::gitea::Gitea;
+use super::github;
+use super::gitlab::GitLab;
+use super::{review_decision, Blocker, CheckStatus, Forge, NewPullRequest, PullRequest, Remote, Review, State};
+
+/// A request the mock server received
+struct Received {
+    method: String,
+    path: String,
+    body: Option<Value>,
+}
+
+/// An HTTP server answering `METHOD /path?query` with canned JSON, and 404 otherwise
+struct Mock {
+    url: String,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl Mock {
+    fn start(routes: &[(&str, u16, Value)]) -> Mock {
+        let routes: HashMap<String, (u16, String)> = routes
+            .iter()
+            .map(|(route, status, body)| (route.to_string(), (*status, body.to_string())))
+            .collect();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let log = Arc::clone(&received);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let (method, path) = (parts.next().unwrap().to_string(), parts.next().unwrap().to_string());
+
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, response) = routes
+                    .get(&format!("{} {}", method, path))
+                    .cloned()
+                    .unwrap_or((404, json!({ "message": "Not Found" }).to_string()));
+                log.lock().unwrap().push(Received {
+                    body: serde_json::from_slice(&body).ok(),
+                    method,
+                    path,
+                });
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+        Mock { url, received }
+    }
+
+    /// The body of the last `method` request to `path`
+    fn body(&self, method: &str, path: &str) -> Option<Value> {
+        let received = self.received.lock().unwrap();
+        let request = received.iter().rev().find(|request| request.method == method && request.path == path)?;
+        request.body.clone()
+    }
+}
+
+fn remote(url: &str) -> Remote {
+    Remote::parse(url).unwrap()
+}
+
+/// Blockers as `hard: ...` or `waiting: ...`
+fn blockers(pr: &PullRequest) -> Vec<String> {
+    pr.blockers()
+        .into_iter()
+        .map(|blocker| match blocker {
+            Blocker::Hard(reason) => format!("hard: {}", reason),
+            Blocker::Waiting(reason) => format!("waiting: {}", reason),
+        })
+        .collect()
+}
+
+#[test]
+fn parses_scp_style_urls() {
+    let remote = remote("git@github.com:owner/repo.git");
+    assert_eq!(remote.host, "github.com");
+    assert_eq!(remote.path, "owner/repo");
+    assert_eq!(remote.owner_and_name(), ("owner", "repo"));
+}
+
+#[test]
+fn parses_ssh_urls_with_user_and_port() {
+    let remote = remote("ssh://git@GitLab.example.com:2222/group/subgroup/repo.git");
+    assert_eq!(remote.host, "gitlab.example.com");
+    assert_eq!(remote.path, "group/subgroup/repo");
+    assert_eq!(remote.owner_and_name(), ("group/subgroup", "repo"));
+}
+
+#[test]
+fn parses_https_urls() {
+    let remote = remote("https://user@codeberg.org/owner/repo/");
+    assert_eq!(remote.host, "codeberg.org");
+    assert_eq!(remote.path, "owner/repo");
+    assert_eq!(self::remote("https://github.com/owner/repo.git").path, "owner/repo");
+}
+
+#[test]
+fn local_paths_are_not_remotes() {
+    assert!(Remote::parse("/srv/git/repo.git").is_none());
+    assert!(Remote::parse("../repo").is_none());
+    assert!(Remote::parse("https://example.com/repo").is_none());
+}
+
+#[test]
+fn no_reviews_need_no_decision() {
+    assert!(review_decision([]) == Review::NotRequired);
+}
+
+#[test]
+fn each_reviewers_latest_review_counts() {
+    let reviews = [("alice", Review::ChangesRequested), ("alice", Review::Approved)];
+    assert!(review_decision(reviews) == Review::Approved);
+
+    let reviews = [("alice", Review::Approved), ("alice", Review::NotRequired)];
+    assert!(review_decision(reviews) == Review::NotRequired);
+}
+
+#[test]
+fn requested_changes_win_over_approvals() {
+    let reviews = [("alice", Review::Approved), ("bob", Review::ChangesRequested), ("carol", Review::Approved)];
+    assert!(review_decision(reviews) == Review::ChangesRequested);
+}
+
+fn github_pull(number: u64) -> Value {
+    json!({
+        "number": number,
+        "node_id": "PR_node",
+        "title": "Add feature",
+        "html_url": format!("https://github.com/o/r/pull/{}", number),
+        "state": "open",
+        "draft": false,
+        "merged_at": null,
+        "mergeable": true,
+        "head": { "ref": "feat", "sha": "abc123" },
+    })
+}
+
+fn github_mock(extra: &[(&str, u16, Value)]) -> Mock {
+    let mut routes = vec![
+        ("GET /repos/o/r/pulls?head=o%3Afeat&state=all&per_page=1", 200, json!([github_pull(7)])),
+        ("GET /repos/o/r/pulls/7", 200, github_pull(7)),
+        (
+            "GET /repos/o/r/pulls/7/reviews?per_page=100&page=1",
+            200,
+            json!([
+                { "state": "CHANGES_REQUESTED", "user": { "login": "alice" } },
+                { "state": "COMMENTED", "user": { "login": "bob" } },
+                { "state": "APPROVED", "user": { "login": "alice" } },
+            ]),
+        ),
+        (
+            "GET /repos/o/r/commits/abc123/check-runs?per_page=100",
+            200,
+            json!({ "check_runs": [
+                { "name": "build", "status": "completed", "conclusion": "success" },
+                { "name": "lint", "status": "in_progress", "conclusion": null },
+            ] }),
+        ),
+        (
+            "GET /repos/o/r/commits/abc123/status",
+            200,
+            json!({ "statuses": [{ "context": "ci/deploy", "state": "success" }] }),
+        ),
+    ];
+    routes.extend(extra.iter().cloned());
+    Mock::start(&routes)
+}
+
+#[test]
+fn github_describes_a_pull_request() {
+    let mock = github_mock(&[]);
+    let forge = github::connect(remote("git@github.com:o/r.git"), Some(&mock.url), Path::new("."));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    assert_eq!(pr.number, 7);
+    assert_eq!(pr.reference, "PR #7");
+    assert_eq!(pr.head, "feat");
+    assert!(pr.state == State::Open);
+    assert_eq!(pr.mergeable, Some(true));
+    assert!(pr.review == Review::Approved);
+    assert_eq!(pr.checks.len(), 3);
+    assert!(pr.checks.iter().any(|check| check.name == "lint" && check.status == CheckStatus::Pending));
+    assert_eq!(blockers(&pr), ["waiting: checks are pending: lint"]);
+}
+
+#[test]
+fn github_reads_every_page_of_reviews() {
+    let comments: Vec<Value> = (0..100)
+        .map(|_| json!({ "state": "COMMENTED", "user": { "login": "bob" } }))
+        .collect();
+    let mock = github_mock(&[
+        ("GET /repos/o/r/pulls/7/reviews?per_page=100&page=1", 200, Value::from(comments)),
+        (
+            "GET /repos/o/r/pulls/7/reviews?per_page=100&page=2",
+            200,
+            json!([{ "state": "CHANGES_REQUESTED", "user": { "login": "carol" } }]),
+        ),
+    ]);
+    let forge = github::connect(remote("git@github.com:o/r.git"), Some(&mock.url), Path::new("."));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    assert!(pr.review == Review::ChangesRequested);
+}
+
+#[test]
+fn github_finds_no_pull_request() {
+    let mock = github_mock(&[("GET /repos/o/r/pulls?head=o%3Aother&state=all&per_page=1", 200, json!([]))]);
+    let forge = github::connect(remote("git@github.com:o/r.git"), Some(&mock.url), Path::new("."));
+    assert!(forge.pull_request("other").unwrap().is_none());
+}
+
+#[test]
+fn github_merges_through_the_rest_api() {
+    let mock = github_mock(&[("PUT /repos/o/r/pulls/7/merge", 200, json!({ "merged": true }))]);
+    let forge = github::connect(remote("git@github.com:o/r.git"), Some(&mock.url), Path::new("."));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    forge.merge(&pr, "squash", false).unwrap();
+    assert_eq!(mock.body("PUT", "/repos/o/r/pulls/7/merge"), Some(json!({ "merge_method": "squash" })));
+}
+
+#[test]
+fn github_enables_auto_merge_through_graphql() {
+    let mock = github_mock(&[("POST /graphql", 200, json!({ "data": {} }))]);
+    let forge = github::connect(remote("git@github.com:o/r.git"), Some(&mock.url), Path::new("."));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    forge.merge(&pr, "rebase", true).unwrap();
+    let body = mock.body("POST", "/graphql").unwrap();
+    assert_eq!(body["variables"], json!({ "id": "PR_node", "method": "REBASE" }));
+}
+
+#[test]
+fn github_reports_a_failed_merge() {
+    let mock = github_mock(&[("PUT /repos/o/r/pulls/7/merge", 405, json!({ "message": "Pull Request is not mergeable" }))]);
+    let forge = github::connect(remote("git@github.com:o/r.git"), Some(&mock.url), Path::new("."));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    let err = forge.merge(&pr, "merge", false).unwrap_err();
+    assert!(format!("{:#}", err).contains("Pull Request is not mergeable"));
+}
+
+const GITLAB_PROJECT: &str = "/projects/group%2Fsub%2Fr";
+
+fn gitlab_mock(merge_request: Value, extra: &[(&str, u16, Value)]) -> Mock {
+    let list = format!(
+        "GET {}/merge_requests?source_branch=feat&state=all&order_by=created_at&sort=desc&per_page=1",
+        GITLAB_PROJECT
+    );
+    let single = format!("GET {}/merge_requests/3", GITLAB_PROJECT);
+    let mut routes = vec![
+        (list.as_str(), 200, json!([merge_request])),
+        (single.as_str(), 200, merge_request),
+    ];
+    routes.extend(extra.iter().cloned());
+    Mock::start(&routes)
+}
+
+fn gitlab_merge_request(fields: Value) -> Value {
+    let mut merge_request = json!({
+        "iid": 3,
+        "title": "Add feature",
+        "web_url": "https://gitlab.example.com/group/sub/r/-/merge_requests/3",
+        "source_branch": "feat",
+        "state": "opened",
+        "draft": false,
+        "has_conflicts": false,
+        "detailed_merge_status": "mergeable",
+        "head_pipeline": { "status": "success" },
+    });
+    for (key, value) in fields.as_object().unwrap() {
+        merge_request[key] = value.clone();
+    }
+    merge_request
+}
+
+#[test]
+fn gitlab_describes_a_blocked_merge_request() {
+    let merge_request = gitlab_merge_request(json!({
+        "has_conflicts": true,
+        "head_pipeline": { "status": "failed" },
+    }));
+    let mock = gitlab_mock(merge_request, &[]);
+    let forge = GitLab::new(remote("git@gitlab.example.com:group/sub/r.git"), Some(&mock.url));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    assert_eq!(pr.reference, "MR !3");
+    assert_eq!(pr.mergeable, Some(false));
+    // Without approval rules, no review is needed
+    assert!(pr.review == Review::NotRequired);
+    assert_eq!(
+        blockers(&pr),
+        ["hard: it conflicts with the base branch", "hard: checks failed: pipeline"]
+    );
+}
+
+#[test]
+fn gitlab_waits_for_approvals() {
+    let approvals = format!("GET {}/merge_requests/3/approvals", GITLAB_PROJECT);
+    let mock = gitlab_mock(
+        gitlab_merge_request(json!({ "detailed_merge_status": "not_approved" })),
+        &[(approvals.as_str(), 200, json!({ "approvals_left": 1, "approved_by": [] }))],
+    );
+    let forge = GitLab::new(remote("git@gitlab.example.com:group/sub/r.git"), Some(&mock.url));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    assert!(pr.review == Review::Required);
+    assert_eq!(blockers(&pr), ["waiting: it needs an approving review"]);
+}
+
+#[test]
+fn gitlab_merges_with_squash() {
+    let path = format!("{}/merge_requests/3/merge", GITLAB_PROJECT);
+    let merge = format!("PUT {}", path);
+    let mock = gitlab_mock(gitlab_merge_request(json!({})), &[(merge.as_str(), 200, json!({ "state": "merged" }))]);
+    let forge = GitLab::new(remote("git@gitlab.example.com:group/sub/r.git"), Some(&mock.url));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    assert!(blockers(&pr).is_empty());
+    forge.merge(&pr, "squash", true).unwrap();
+    assert_eq!(
+        mock.body("PUT", &path),
+        Some(json!({ "squash": true, "merge_when_pipeline_succeeds": true }))
+    );
+}
+
+fn gitea_pull(number: u64, branch: &str, fields: Value) -> Value {
+    let mut pull = json!({
+        "number": number,
+        "title": "Add feature",
+        "html_url": format!("https://gitea.example.com/o/r/pulls/{}", number),
+        "state": "open",
+        "merged": false,
+        "head": { "ref": branch, "sha": format!("sha{}", number) },
+    });
+    for (key, value) in fields.as_object().unwrap() {
+        pull[key] = value.clone();
+    }
+    pull
+}
+
+fn gitea_mock(pull: Value, extra: &[(&str, u16, Value)]) -> Mock {
+    let mut routes = vec![
+        (
+            "GET /repos/o/r/pulls?state=all&limit=50&page=1",
+            200,
+            json!([gitea_pull(4, "other", json!({})), pull]),
+        ),
+        (
+            "GET /repos/o/r/pulls/5/reviews?limit=50&page=1",
+            200,
+            json!([
+                { "state": "REQUEST_CHANGES", "dismissed": true, "user": { "login": "bob" } },
+                { "state": "APPROVED", "user": { "login": "carol" } },
+            ]),
+        ),
+        (
+            "GET /repos/o/r/commits/sha5/status",
+            200,
+            json!({ "statuses": [{ "context": "ci", "status": "pending" }] }),
+        ),
+    ];
+    routes.extend(extra.iter().cloned());
+    Mock::start(&routes)
+}
+
+#[test]
+fn gitea_treats_unknown_mergeability_as_unknown() {
+    let mock = gitea_mock(gitea_pull(5, "feat", json!({})), &[]);
+    let forge = Gitea::new(remote("git@gitea.example.com:o/r.git"), Some(&mock.url));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    assert_eq!(pr.number, 5);
+    assert_eq!(pr.mergeable, None);
+    // A dismissed request for changes no longer counts
+    assert!(pr.review == Review::Approved);
+    assert_eq!(blockers(&pr), ["waiting: checks are pending: ci"]);
+}
+
+#[test]
+fn gitea_blocks_drafts_and_conflicts() {
+    let pull = gitea_pull(5, "feat", json!({ "title": "WIP: Add feature", "mergeable": false }));
+    let mock = gitea_mock(pull, &[]);
+    let forge = Gitea::new(remote("git@gitea.example.com:o/r.git"), Some(&mock.url));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    assert!(pr.draft);
+    assert_eq!(
+        blockers(&pr),
+        [
+            "hard: it is a draft",
+            "hard: it conflicts with the base branch",
+            "waiting: checks are pending: ci",
+        ]
+    );
+}
+
+#[test]
+fn gitea_finds_labels_on_later_pages() {
+    let labels: Vec<Value> = (0..50).map(|id| json!({ "id": id, "name": format!("label-{}", id) })).collect();
+    let mock = gitea_mock(
+        gitea_pull(5, "feat", json!({})),
+        &[
+            ("GET /repos/o/r/labels?limit=50&page=1", 200, Value::from(labels)),
+            ("GET /repos/o/r/labels?limit=50&page=2", 200, json!([{ "id": 99, "name": "bug" }])),
+            ("POST /repos/o/r/pulls", 201, json!({ "number": 5 })),
+        ],
+    );
+    let forge = Gitea::new(remote("git@gitea.example.com:o/r.git"), Some(&mock.url));
+
+    let new = NewPullRequest {
+        branch: "feat".to_string(),
+        base: "main".to_string(),
+        title: "Add feature".to_string(),
+        body: String::new(),
+        draft: false,
+        reviewers: Vec::new(),
+        labels: vec!["bug".to_string()],
+    };
+    forge.create_pull_request(&new).unwrap();
+    assert_eq!(mock.body("POST", "/repos/o/r/pulls").unwrap()["labels"], json!([99]));
+}
+
+#[test]
+fn gitea_finds_no_pull_request() {
+    let mock = gitea_mock(gitea_pull(5, "feat", json!({})), &[]);
+    let forge = Gitea::new(remote("git@gitea.example.com:o/r.git"), Some(&mock.url));
+    assert!(forge.pull_request("missing").unwrap().is_none());
+}
+
+#[test]
+fn gitea_merges_when_checks_succeed() {
+    let mock = gitea_mock(
+        gitea_pull(5, "feat", json!({ "mergeable": true })),
+        &[("POST /repos/o/r/pulls/5/merge", 200, json!({}))],
+    );
+    let forge = Gitea::new(remote("git@gitea.example.com:o/r.git"), Some(&mock.url));
+
+    let pr = forge.pull_request("feat").unwrap().unwrap();
+    forge.merge(&pr, "merge", true).unwrap();
+    assert_eq!(
+        mock.body("POST", "/repos/o/r/pulls/5/merge"),
+        Some(json!({ "Do": "merge", "merge_when_checks_succeed": true }))
+    );
+}
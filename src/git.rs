@@ -0,0 +1,201 @@
+//! Read access to repository state.
+//!
+//! Queries go through a [`GitBackend`]. When built with the `libgit2` feature
+//! the repository is read in-process; otherwise, or when libgit2 cannot open
+//! the repository, every query spawns the `git` CLI. Set
+//! `WORKTREE_GIT_BACKEND=cli` to force the CLI backend.
+//!
+//! Commands that change the repository (worktree add, fetch, push, commit,
+//! branch deletion and so on) run the `git` CLI directly, so that hooks,
+//! credentials and the user's config apply. Their output is shown to the
+//! user, never parsed; only the exit status decides what happens next.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use crate::worktrees::Worktree;
+
+mod cli;
+#[cfg(feature = "libgit2")]
+mod libgit2;
+
+pub use cli::{is_squash_merged, remote_has_branch, CliBackend};
+#[cfg(feature = "libgit2")]
+pub use self::libgit2::Libgit2Backend;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GitError {
+    #[error("Not in a git repository")]
+    NotARepository,
+    #[error("Unknown revision '{0}'")]
+    RevisionNotFound(String),
+    #[error("git {command} failed: {stderr}")]
+    CommandFailed { command: String, stderr: String },
+    #[error("Failed to execute git: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid UTF-8 in git output")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    #[cfg(feature = "libgit2")]
+    #[error(transparent)]
+    Libgit2(#[from] ::git2::Error),
+}
+
+/// Where forges publish the head of each pull request. `wt add --pr` fetches
+/// them to the same names locally, so commits there count as pushed.
+pub const PULL_REQUEST_REFS: &[&str] = &["refs/pull/*", "refs/merge-requests/*"];
+
+pub type Result<T, E = GitError> = std::result::Result<T, E>;
+
+/// A commit as shown to users
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub hash: String,
+    pub short_hash: String,
+    /// Committer time, seconds since the Unix epoch
+    pub timestamp: i64,
+    pub subject: String,
+}
+
+/// A commit's message and author, for describing a branch's work
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// `Name <email>`
+    pub author: String,
+    pub subject: String,
+    /// The message after the subject, trailers included
+    pub body: String,
+    /// Values of the `Co-authored-by` trailers
+    pub co_authors: Vec<String>,
+}
+
+/// Uncommitted changes and upstream divergence of a worktree
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    /// Staged, modified, deleted or conflicted paths
+    pub dirty: u32,
+    /// Untracked paths relative to the worktree root; an untracked
+    /// directory is listed once rather than file by file
+    pub untracked: Vec<String>,
+    pub upstream: Option<Divergence>,
+}
+
+/// How far HEAD is ahead of and behind another ref
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub refname: String,
+    pub ahead: u32,
+    pub behind: u32,
+}
+
+/// An entry in the stash list
+#[derive(Debug, Clone)]
+pub struct Stash {
+    /// Position in the stash list, as in `stash@{0}`
+    pub index: usize,
+    /// Reflog message, e.g. `WIP on main: abc1234 subject`
+    pub message: String,
+}
+
+impl Stash {
+    /// Whether the stash was made while `branch` was checked out
+    pub fn is_on_branch(&self, branch: &str) -> bool {
+        // git stash records "WIP on <branch>: ..." or "On <branch>: ..."
+        self.message
+            .strip_prefix("WIP on ")
+            .or_else(|| self.message.strip_prefix("On "))
+            .and_then(|rest| rest.strip_prefix(branch))
+            .is_some_and(|rest| rest.starts_with(':'))
+    }
+}
+
+/// Repository queries. Methods taking `dir` answer for the worktree
+/// containing it, since HEAD, status and so on differ per worktree; the
+/// others answer for the repository containing the current directory.
+pub trait GitBackend {
+    /// Absolute path of the `.git` directory shared by all worktrees
+    fn common_dir(&self) -> Result<PathBuf>;
+
+    /// Every worktree of the repository, the main worktree first
+    fn worktrees(&self) -> Result<Vec<Worktree>>;
+
+    /// Whether a fully qualified ref like `refs/heads/main` exists
+    fn ref_exists(&self, refname: &str) -> Result<bool>;
+
+    /// Short names of local branches, remote-tracking branches and tags
+    fn refs(&self) -> Result<Vec<String>>;
+
+    /// Resolve `rev` to a commit
+    fn commit(&self, dir: &Path, rev: &str) -> Result<Commit>;
+
+    /// Short name of the branch checked out in `dir`, `None` when detached
+    fn current_branch(&self, dir: &Path) -> Result<Option<String>>;
+
+    /// Uncommitted changes in `dir` and how HEAD compares to its upstream
+    fn status(&self, dir: &Path) -> Result<Status>;
+
+    /// How far HEAD in `dir` is ahead of and behind `base`
+    fn divergence(&self, dir: &Path, base: &str) -> Result<Divergence>;
+
+    /// Commits on HEAD in `dir` that no remote-tracking branch, fetched pull
+    /// request and not `base` contain, i.e. work that exists only in this
+    /// branch, newest first
+    fn unpushed_commits(&self, dir: &Path, base: &str) -> Result<Vec<Commit>>;
+
+    /// Commits on `branch` that `base` doesn't contain, oldest first, leaving out merges
+    fn messages(&self, dir: &Path, base: &str, branch: &str) -> Result<Vec<Message>>;
+
+    /// Paths with unresolved merge conflicts in `dir`
+    fn conflicted_files(&self, dir: &Path) -> Result<Vec<String>>;
+
+    /// Whether a rebase has stopped in `dir`
+    fn rebase_in_progress(&self, dir: &Path) -> Result<bool>;
+
+    /// Every stash entry, newest first
+    fn stashes(&self) -> Result<Vec<Stash>>;
+
+    /// Whether `ancestor` is reachable from `descendant`, i.e. already merged into it
+    fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool>;
+
+    /// Whether a local branch still points where it was created, according
+    /// to its reflog. `false` when there is no reflog to tell.
+    fn branch_never_moved(&self, branch: &str) -> Result<bool>;
+
+    /// Whether a local branch has an upstream configured whose
+    /// remote-tracking branch no longer exists
+    fn upstream_gone(&self, branch: &str) -> Result<bool>;
+
+    /// Short name of the upstream of a local branch, e.g. `origin/main`
+    fn upstream(&self, branch: &str) -> Result<Option<String>>;
+
+    /// The ref a local branch pulls from on its remote (`branch.<name>.merge`),
+    /// e.g. `refs/heads/main` or `refs/pull/12/head`
+    fn merge_ref(&self, branch: &str) -> Result<Option<String>>;
+
+    /// Names of the configured remotes
+    fn remotes(&self) -> Result<Vec<String>>;
+
+    /// URL of a remote, `None` if there is no such remote
+    fn remote_url(&self, remote: &str) -> Result<Option<String>>;
+
+    /// Value of a config key like `user.email`, `None` if it isn't set
+    fn config(&self, key: &str) -> Result<Option<String>>;
+
+    /// `Name <email>` of whoever git would record as the author of a commit now
+    fn author(&self) -> Result<String>;
+
+    /// `Name <email>` of whoever git would record as the committer of a commit now
+    fn committer(&self) -> Result<String>;
+}
+
+/// The backend to use for the current process
+pub fn backend() -> Box<dyn GitBackend> {
+    if env::var("WORKTREE_GIT_BACKEND").as_deref() == Ok("cli") {
+        return Box::new(CliBackend);
+    }
+
+    #[cfg(feature = "libgit2")]
+    if let Some(backend) = Libgit2Backend::open() {
+        return Box::new(backend);
+    }
+
+    Box::new(CliBackend)
+}
@@ -0,0 +1,378 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use super::{Commit, Divergence, GitBackend, GitError, Message, Result, Stash, Status, PULL_REQUEST_REFS};
+use crate::worktrees::Worktree;
+
+/// Answers every query by running the `git` CLI
+pub struct CliBackend;
+
+impl GitBackend for CliBackend {
+    fn common_dir(&self) -> Result<PathBuf> {
+        let output = git(None, &["rev-parse", "--path-format=absolute", "--git-common-dir"])?;
+        if !output.status.success() {
+            return Err(GitError::NotARepository);
+        }
+        Ok(PathBuf::from(stdout(output)?.trim()))
+    }
+
+    fn worktrees(&self) -> Result<Vec<Worktree>> {
+        let output = run(None, &["worktree", "list", "--porcelain", "-z"])?;
+        Ok(parse_porcelain(&output))
+    }
+
+    fn ref_exists(&self, refname: &str) -> Result<bool> {
+        let output = git(None, &["show-ref", "--verify", "--quiet", refname])?;
+        Ok(output.status.success())
+    }
+
+    fn refs(&self) -> Result<Vec<String>> {
+        // Symbolic refs like origin/HEAD print as empty lines
+        let format = "--format=%(if)%(symref)%(then)%(else)%(refname:short)%(end)";
+        let output = run(None, &["for-each-ref", format, "refs/heads", "refs/remotes", "refs/tags"])?;
+        Ok(output.lines().filter(|name| !name.is_empty()).map(str::to_string).collect())
+    }
+
+    fn commit(&self, dir: &Path, rev: &str) -> Result<Commit> {
+        let output = git(
+            Some(dir),
+            &["log", "-1", "--format=%H%x00%h%x00%ct%x00%s", &format!("{}^{{commit}}", rev), "--"],
+        )?;
+        if !output.status.success() {
+            return Err(GitError::RevisionNotFound(rev.to_string()));
+        }
+
+        let stdout = stdout(output)?;
+        let mut fields = stdout.trim_end_matches('\n').splitn(4, '\0');
+        let mut next = || fields.next().unwrap_or_default().to_string();
+        Ok(Commit {
+            hash: next(),
+            short_hash: next(),
+            timestamp: next().parse().unwrap_or_default(),
+            subject: next(),
+        })
+    }
+
+    fn current_branch(&self, dir: &Path) -> Result<Option<String>> {
+        let output = git(Some(dir), &["symbolic-ref", "--quiet", "--short", "HEAD"])?;
+        // symbolic-ref exits 1 when HEAD is detached
+        match output.status.code() {
+            Some(0) => Ok(Some(stdout(output)?.trim().to_string())),
+            Some(1) => Ok(None),
+            _ => Err(failed("symbolic-ref", output)),
+        }
+    }
+
+    fn status(&self, dir: &Path) -> Result<Status> {
+        let output = run(Some(dir), &["status", "--porcelain=v2", "--branch", "-z"])?;
+
+        let mut status = Status::default();
+        let mut upstream_name = None;
+        let mut records = output.split('\0');
+
+        while let Some(record) = records.next() {
+            if let Some(name) = record.strip_prefix("# branch.upstream ") {
+                upstream_name = Some(name.to_string());
+            } else if let Some(counts) = record.strip_prefix("# branch.ab ") {
+                // Format: "+<ahead> -<behind>"
+                let mut parts = counts.split_whitespace();
+                let ahead = parts.next().and_then(|a| a.trim_start_matches('+').parse().ok());
+                let behind = parts.next().and_then(|b| b.trim_start_matches('-').parse().ok());
+                if let (Some(refname), Some(ahead), Some(behind)) = (upstream_name.take(), ahead, behind) {
+                    status.upstream = Some(Divergence { refname, ahead, behind });
+                }
+            } else if let Some(path) = record.strip_prefix("? ") {
+                status.untracked.push(path.to_string());
+            } else if record.starts_with("2 ") {
+                // Renames and copies are followed by the original path
+                records.next();
+                status.dirty += 1;
+            } else if !record.is_empty() && !record.starts_with('#') && !record.starts_with("! ") {
+                status.dirty += 1;
+            }
+        }
+
+        Ok(status)
+    }
+
+    fn divergence(&self, dir: &Path, base: &str) -> Result<Divergence> {
+        let output = run(
+            Some(dir),
+            &["rev-list", "--left-right", "--count", &format!("HEAD...{}", base)],
+        )?;
+
+        let mut counts = output.split_whitespace().map(|c| c.parse().unwrap_or_default());
+        Ok(Divergence {
+            refname: base.to_string(),
+            ahead: counts.next().unwrap_or_default(),
+            behind: counts.next().unwrap_or_default(),
+        })
+    }
+
+    fn unpushed_commits(&self, dir: &Path, base: &str) -> Result<Vec<Commit>> {
+        let globs: Vec<String> = PULL_REQUEST_REFS.iter().map(|glob| format!("--glob={}", glob)).collect();
+        let mut args = vec!["log", "-z", "--format=%H%x00%h%x00%ct%x00%s", "HEAD", "--not", "--remotes"];
+        args.extend(globs.iter().map(String::as_str));
+        args.extend([base, "--"]);
+        let output = run(Some(dir), &args)?;
+
+        let mut fields = output.split('\0');
+        let mut commits = Vec::new();
+        while let (Some(hash), Some(short_hash), Some(timestamp), Some(subject)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        {
+            commits.push(Commit {
+                hash: hash.to_string(),
+                short_hash: short_hash.to_string(),
+                timestamp: timestamp.parse().unwrap_or_default(),
+                subject: subject.to_string(),
+            });
+        }
+        Ok(commits)
+    }
+
+    fn messages(&self, dir: &Path, base: &str, branch: &str) -> Result<Vec<Message>> {
+        let format = "--format=%an <%ae>%x00%s%x00%b%x00%(trailers:key=Co-authored-by,valueonly,separator=%x1f)%x1e";
+        let range = format!("{}..{}", base, branch);
+        let output = run(Some(dir), &["log", "--reverse", "--no-merges", format, &range, "--"])?;
+
+        let mut messages = Vec::new();
+        for record in output.split('\x1e') {
+            let mut fields = record.trim_start_matches('\n').splitn(4, '\0');
+            let (Some(author), Some(subject), Some(body), Some(co_authors)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            messages.push(Message {
+                author: author.to_string(),
+                subject: subject.to_string(),
+                body: body.trim().to_string(),
+                co_authors: co_authors
+                    .split('\x1f')
+                    .map(str::trim)
+                    .filter(|co_author| !co_author.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            });
+        }
+        Ok(messages)
+    }
+
+    fn conflicted_files(&self, dir: &Path) -> Result<Vec<String>> {
+        let output = run(Some(dir), &["diff", "--name-only", "--diff-filter=U", "-z"])?;
+        Ok(output.split('\0').filter(|path| !path.is_empty()).map(str::to_string).collect())
+    }
+
+    fn rebase_in_progress(&self, dir: &Path) -> Result<bool> {
+        for name in ["rebase-merge", "rebase-apply"] {
+            let path = run(Some(dir), &["rev-parse", "--git-path", name])?;
+            if dir.join(path.trim_end_matches('\n')).exists() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn stashes(&self) -> Result<Vec<Stash>> {
+        let output = run(None, &["stash", "list", "-z", "--format=%gs"])?;
+        Ok(output
+            .split('\0')
+            .filter(|message| !message.is_empty())
+            .enumerate()
+            .map(|(index, message)| Stash { index, message: message.to_string() })
+            .collect())
+    }
+
+    fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+        let output = git(None, &["merge-base", "--is-ancestor", ancestor, descendant])?;
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            _ => Err(failed("merge-base", output)),
+        }
+    }
+
+    fn branch_never_moved(&self, branch: &str) -> Result<bool> {
+        let refname = format!("refs/heads/{}", branch);
+        let output = git(None, &["reflog", "show", "--format=%H", &refname, "--"])?;
+        if !output.status.success() {
+            return Ok(false);
+        }
+        Ok(stdout(output)?.lines().count() == 1)
+    }
+
+    fn upstream_gone(&self, branch: &str) -> Result<bool> {
+        let refname = format!("refs/heads/{}", branch);
+        let output = run(None, &["for-each-ref", "--format=%(upstream:track,nobracket)", &refname])?;
+        Ok(output.trim() == "gone")
+    }
+
+    fn upstream(&self, branch: &str) -> Result<Option<String>> {
+        let output = git(
+            None,
+            &["rev-parse", "--abbrev-ref", "--verify", "--quiet", &format!("{}@{{upstream}}", branch)],
+        )?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(stdout(output)?.trim().to_string()))
+    }
+
+    fn merge_ref(&self, branch: &str) -> Result<Option<String>> {
+        let output = git(None, &["config", "--get", &format!("branch.{}.merge", branch)])?;
+        // config exits 1 when the key isn't set
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(stdout(output)?.trim().to_string()))
+    }
+
+    fn remotes(&self) -> Result<Vec<String>> {
+        let output = run(None, &["remote"])?;
+        Ok(output.lines().map(str::to_string).collect())
+    }
+
+    fn remote_url(&self, remote: &str) -> Result<Option<String>> {
+        if !self.remotes()?.iter().any(|r| r == remote) {
+            return Ok(None);
+        }
+        let output = run(None, &["remote", "get-url", remote])?;
+        Ok(Some(output.trim().to_string()))
+    }
+
+    fn config(&self, key: &str) -> Result<Option<String>> {
+        let output = git(None, &["config", "--get", key])?;
+        // config exits 1 when the key isn't set
+        match output.status.code() {
+            Some(0) => Ok(Some(stdout(output)?.trim().to_string())),
+            Some(1) => Ok(None),
+            _ => Err(failed("config", output)),
+        }
+    }
+
+    fn author(&self) -> Result<String> {
+        ident("GIT_AUTHOR_IDENT")
+    }
+
+    fn committer(&self) -> Result<String> {
+        ident("GIT_COMMITTER_IDENT")
+    }
+}
+
+/// Ask a remote whether it still has `branch`. This always goes over the
+/// network through the CLI, which knows the user's transports and credentials.
+pub fn remote_has_branch(dir: &Path, remote: &str, branch: &str) -> Result<bool> {
+    let refname = format!("refs/heads/{}", branch);
+    let output = git(Some(dir), &["ls-remote", "--exit-code", "--heads", remote, &refname])?;
+    // --exit-code makes ls-remote exit 2 when no ref matched
+    match output.status.code() {
+        Some(0) => Ok(true),
+        Some(2) => Ok(false),
+        _ => Err(failed("ls-remote", output)),
+    }
+}
+
+/// Whether the changes on `branch` have landed in `base` as different commits,
+/// as happens with squash merges and rebase merges. Creates a dangling commit.
+pub fn is_squash_merged(branch: &str, base: &str) -> Result<bool> {
+    let merge_base = run(None, &["merge-base", base, branch])?;
+    let merge_base = merge_base.trim();
+    let tree = run(None, &["rev-parse", &format!("{}^{{tree}}", branch)])?;
+    let tree = tree.trim();
+
+    // A commit on base with exactly the branch's tree, e.g. after a rebase merge
+    let base_trees = run(None, &["log", "--format=%T", &format!("{}..{}", merge_base, base)])?;
+    if base_trees.lines().any(|base_tree| base_tree == tree) {
+        return Ok(true);
+    }
+
+    // Squash the branch into a single commit and ask whether base already
+    // has a commit with the same patch-id
+    let squashed = run(None, &["commit-tree", tree, "-p", merge_base, "-m", "squash"])?;
+    let cherry = run(None, &["cherry", base, squashed.trim()])?;
+    Ok(cherry.starts_with('-'))
+}
+
+/// `Name <email>` from `git var`, which honours GIT_AUTHOR_NAME and the like
+fn ident(var: &str) -> Result<String> {
+    let output = run(None, &["var", var])?;
+    // The ident ends with a timestamp and timezone
+    let mut fields = output.trim().rsplitn(3, ' ');
+    Ok(fields.nth(2).unwrap_or_default().to_string())
+}
+
+/// Run git, returning its raw output whatever the exit status
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<Output> {
+    let mut cmd = Command::new("git");
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    Ok(cmd.args(args).output()?)
+}
+
+/// Run git and return its stdout, failing on a non-zero exit status
+fn run(dir: Option<&Path>, args: &[&str]) -> Result<String> {
+    let output = git(dir, args)?;
+    if !output.status.success() {
+        return Err(failed(args[0], output));
+    }
+    stdout(output)
+}
+
+fn stdout(output: Output) -> Result<String> {
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+fn failed(command: &str, output: Output) -> GitError {
+    GitError::CommandFailed {
+        command: command.to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    }
+}
+
+/// Parse `git worktree list --porcelain -z` output. Each attribute is
+/// NUL-terminated and an empty attribute ends a worktree's record.
+fn parse_porcelain(output: &str) -> Vec<Worktree> {
+    let mut worktrees = Vec::new();
+    let mut current: Option<Worktree> = None;
+
+    for field in output.split('\0') {
+        if field.is_empty() {
+            worktrees.extend(current.take());
+            continue;
+        }
+
+        let (key, value) = match field.split_once(' ') {
+            Some((key, value)) => (key, Some(value)),
+            None => (field, None),
+        };
+
+        if key == "worktree" {
+            worktrees.extend(current.take());
+            current = Some(Worktree {
+                path: PathBuf::from(value.unwrap_or_default()),
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let Some(wt) = current.as_mut() else {
+            continue;
+        };
+
+        match key {
+            "HEAD" => wt.head = value.map(str::to_string),
+            "branch" => {
+                wt.branch = value.map(|b| b.strip_prefix("refs/heads/").unwrap_or(b).to_string())
+            }
+            "bare" => wt.bare = true,
+            "detached" => wt.detached = true,
+            "locked" => wt.locked = Some(value.unwrap_or_default().to_string()),
+            "prunable" => wt.prunable = Some(value.unwrap_or_default().to_string()),
+            _ => {}
+        }
+    }
+    worktrees.extend(current);
+
+    worktrees
+}
@@ -0,0 +1,391 @@
+use git2::{BranchType, ErrorCode, Repository, RepositoryState, Sort, StatusOptions, WorktreeLockStatus};
+use std::path::{Path, PathBuf};
+use super::{CliBackend, Commit, Divergence, GitBackend, GitError, Message, Result, Stash, Status, PULL_REQUEST_REFS};
+use crate::worktrees::Worktree;
+
+/// Reads the repository in-process through libgit2
+pub struct Libgit2Backend {
+    repo: Repository,
+}
+
+impl Libgit2Backend {
+    /// Open the repository containing the current directory, or `None` if
+    /// libgit2 can't, in which case the CLI backend should be used instead
+    pub fn open() -> Option<Self> {
+        let repo = Repository::discover(".").ok()?;
+        Some(Libgit2Backend { repo })
+    }
+
+    fn open_dir(&self, dir: &Path) -> Result<Repository> {
+        Repository::discover(dir).map_err(|err| match err.code() {
+            ErrorCode::NotFound => GitError::NotARepository,
+            _ => err.into(),
+        })
+    }
+}
+
+impl GitBackend for Libgit2Backend {
+    fn common_dir(&self) -> Result<PathBuf> {
+        Ok(normalize(self.repo.commondir()))
+    }
+
+    fn worktrees(&self) -> Result<Vec<Worktree>> {
+        let main_repo = Repository::open(self.repo.commondir())?;
+        let mut worktrees = Vec::new();
+
+        if main_repo.is_bare() {
+            worktrees.push(Worktree {
+                path: normalize(main_repo.path()),
+                bare: true,
+                ..Default::default()
+            });
+        } else if let Some(workdir) = main_repo.workdir() {
+            worktrees.push(describe_head(&main_repo, normalize(workdir)));
+        }
+
+        let mut linked_worktrees = Vec::new();
+        for name in main_repo.worktrees()?.iter() {
+            let Some(name) = name? else {
+                continue;
+            };
+            let linked = main_repo.find_worktree(name)?;
+            let path = normalize(linked.path());
+
+            let mut wt = match linked.validate() {
+                Ok(()) => describe_head(&Repository::open_from_worktree(&linked)?, path),
+                // The checkout is gone, but its HEAD is still in the admin directory
+                Err(_) => Worktree {
+                    prunable: Some("gitdir file points to non-existent location".to_string()),
+                    ..read_head_file(&main_repo, name, path)
+                },
+            };
+            if let WorktreeLockStatus::Locked(reason) = linked.is_locked()? {
+                wt.locked = Some(reason.unwrap_or_default().trim_end().to_string());
+            }
+            linked_worktrees.push(wt);
+        }
+
+        // Match the order of `git worktree list`
+        linked_worktrees.sort_by(|a, b| a.path.cmp(&b.path));
+        worktrees.extend(linked_worktrees);
+
+        Ok(worktrees)
+    }
+
+    fn ref_exists(&self, refname: &str) -> Result<bool> {
+        match self.repo.find_reference(refname) {
+            Ok(_) => Ok(true),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn refs(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for glob in ["refs/heads/*", "refs/remotes/*", "refs/tags/*"] {
+            for reference in self.repo.references_glob(glob)? {
+                let reference = reference?;
+                // Skip symbolic refs like origin/HEAD, as for-each-ref does
+                if reference.symbolic_target().is_ok_and(|target| target.is_some()) {
+                    continue;
+                }
+                names.push(reference.shorthand()?.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    fn commit(&self, dir: &Path, rev: &str) -> Result<Commit> {
+        let repo = self.open_dir(dir)?;
+        let object = repo.revparse_single(rev).map_err(|err| match err.code() {
+            ErrorCode::NotFound | ErrorCode::InvalidSpec | ErrorCode::Ambiguous => {
+                GitError::RevisionNotFound(rev.to_string())
+            }
+            _ => err.into(),
+        })?;
+        let commit = object
+            .peel_to_commit()
+            .map_err(|_| GitError::RevisionNotFound(rev.to_string()))?;
+        to_commit(&commit)
+    }
+
+    fn current_branch(&self, dir: &Path) -> Result<Option<String>> {
+        Ok(head_branch(&self.open_dir(dir)?))
+    }
+
+    fn status(&self, dir: &Path) -> Result<Status> {
+        let repo = self.open_dir(dir)?;
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).include_ignored(false);
+
+        let mut status = Status::default();
+        for entry in repo.statuses(Some(&mut options))?.iter() {
+            let flags = entry.status();
+            if flags.is_wt_new() {
+                status.untracked.extend(entry.path().map(str::to_string));
+            } else if !flags.is_ignored() {
+                status.dirty += 1;
+            }
+        }
+
+        if let Some(branch) = head_branch(&repo) {
+            let local = repo.find_branch(&branch, BranchType::Local);
+            if let Ok(upstream) = local.and_then(|local| local.upstream()) {
+                let refname = upstream.name()?.unwrap_or_default().to_string();
+                if let (Some(head), Some(target)) = (repo.head()?.target(), upstream.get().target()) {
+                    let (ahead, behind) = repo.graph_ahead_behind(head, target)?;
+                    status.upstream = Some(Divergence {
+                        refname,
+                        ahead: ahead as u32,
+                        behind: behind as u32,
+                    });
+                }
+            }
+        }
+
+        Ok(status)
+    }
+
+    fn divergence(&self, dir: &Path, base: &str) -> Result<Divergence> {
+        let repo = self.open_dir(dir)?;
+        let head = repo.head()?.peel_to_commit()?.id();
+        let base_commit = repo
+            .revparse_single(base)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| GitError::RevisionNotFound(base.to_string()))?;
+        let (ahead, behind) = repo.graph_ahead_behind(head, base_commit.id())?;
+
+        Ok(Divergence {
+            refname: base.to_string(),
+            ahead: ahead as u32,
+            behind: behind as u32,
+        })
+    }
+
+    fn unpushed_commits(&self, dir: &Path, base: &str) -> Result<Vec<Commit>> {
+        let repo = self.open_dir(dir)?;
+        let mut walk = repo.revwalk()?;
+        walk.push_head()?;
+        walk.hide_glob("refs/remotes/*")?;
+        for glob in PULL_REQUEST_REFS {
+            walk.hide_glob(glob)?;
+        }
+        let base_commit = repo
+            .revparse_single(base)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| GitError::RevisionNotFound(base.to_string()))?;
+        walk.hide(base_commit.id())?;
+
+        let mut commits = Vec::new();
+        for oid in walk {
+            commits.push(to_commit(&repo.find_commit(oid?)?)?);
+        }
+        Ok(commits)
+    }
+
+    fn messages(&self, dir: &Path, base: &str, branch: &str) -> Result<Vec<Message>> {
+        let repo = self.open_dir(dir)?;
+        let resolve = |rev: &str| {
+            repo.revparse_single(rev)
+                .and_then(|object| object.peel_to_commit())
+                .map(|commit| commit.id())
+                .map_err(|_| GitError::RevisionNotFound(rev.to_string()))
+        };
+        let mut walk = repo.revwalk()?;
+        walk.set_sorting(Sort::TIME | Sort::REVERSE)?;
+        walk.push(resolve(branch)?)?;
+        walk.hide(resolve(base)?)?;
+
+        let mut messages = Vec::new();
+        for oid in walk {
+            let commit = repo.find_commit(oid?)?;
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            let author = commit.author();
+            let co_authors = git2::message_trailers_strs(commit.message()?)?
+                .iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case("Co-authored-by"))
+                .map(|(_, value)| value.trim().to_string())
+                .collect();
+            messages.push(Message {
+                author: format!("{} <{}>", author.name()?, author.email()?),
+                subject: commit.summary()?.unwrap_or_default().to_string(),
+                body: commit.body()?.unwrap_or_default().trim().to_string(),
+                co_authors,
+            });
+        }
+        Ok(messages)
+    }
+
+    fn conflicted_files(&self, dir: &Path) -> Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for conflict in self.open_dir(dir)?.index()?.conflicts()? {
+            let conflict = conflict?;
+            if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                paths.push(String::from_utf8(entry.path)?);
+            }
+        }
+        Ok(paths)
+    }
+
+    fn rebase_in_progress(&self, dir: &Path) -> Result<bool> {
+        // git am stops in rebase-apply too, which the CLI backend counts as well
+        Ok(matches!(
+            self.open_dir(dir)?.state(),
+            RepositoryState::Rebase
+                | RepositoryState::RebaseInteractive
+                | RepositoryState::RebaseMerge
+                | RepositoryState::ApplyMailbox
+                | RepositoryState::ApplyMailboxOrRebase
+        ))
+    }
+
+    fn stashes(&self) -> Result<Vec<Stash>> {
+        // Iterating the stash needs a mutable handle
+        let mut repo = Repository::open(self.repo.commondir())?;
+        let mut stashes = Vec::new();
+        repo.stash_foreach(|index, message, _| {
+            stashes.push(Stash { index, message: message.to_string() });
+            true
+        })?;
+        Ok(stashes)
+    }
+
+    fn is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool> {
+        let resolve = |rev: &str| {
+            self.repo
+                .revparse_single(rev)
+                .and_then(|object| object.peel_to_commit())
+                .map(|commit| commit.id())
+                .map_err(|_| GitError::RevisionNotFound(rev.to_string()))
+        };
+        let (ancestor, descendant) = (resolve(ancestor)?, resolve(descendant)?);
+        Ok(ancestor == descendant || self.repo.graph_descendant_of(descendant, ancestor)?)
+    }
+
+    fn branch_never_moved(&self, branch: &str) -> Result<bool> {
+        let reflog = self.repo.reflog(&format!("refs/heads/{}", branch))?;
+        Ok(reflog.len() == 1)
+    }
+
+    fn upstream_gone(&self, branch: &str) -> Result<bool> {
+        let refname = format!("refs/heads/{}", branch);
+        let upstream = match self.repo.branch_upstream_name(&refname) {
+            Ok(upstream) => upstream,
+            // No upstream configured at all
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(!self.ref_exists(upstream.as_str()?)?)
+    }
+
+    fn upstream(&self, branch: &str) -> Result<Option<String>> {
+        let local = match self.repo.find_branch(branch, BranchType::Local) {
+            Ok(local) => local,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        match local.upstream() {
+            Ok(upstream) => Ok(upstream.name()?.map(str::to_string)),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn merge_ref(&self, branch: &str) -> Result<Option<String>> {
+        match self.repo.config()?.get_string(&format!("branch.{}.merge", branch)) {
+            Ok(merge) => Ok(Some(merge)),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn remotes(&self) -> Result<Vec<String>> {
+        let mut remotes = Vec::new();
+        for remote in self.repo.remotes()?.iter() {
+            remotes.extend(remote?.map(str::to_string));
+        }
+        Ok(remotes)
+    }
+
+    fn remote_url(&self, remote: &str) -> Result<Option<String>> {
+        match self.repo.find_remote(remote) {
+            Ok(remote) => Ok(Some(remote.url()?.to_string())),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn config(&self, key: &str) -> Result<Option<String>> {
+        match self.repo.config()?.get_string(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // libgit2 ignores GIT_AUTHOR_NAME, EMAIL and the other fallbacks git has
+    fn author(&self) -> Result<String> {
+        CliBackend.author()
+    }
+
+    fn committer(&self) -> Result<String> {
+        CliBackend.committer()
+    }
+}
+
+fn to_commit(commit: &git2::Commit) -> Result<Commit> {
+    let short_hash = commit.as_object().short_id()?;
+    Ok(Commit {
+        hash: commit.id().to_string(),
+        short_hash: short_hash.as_str()?.to_string(),
+        timestamp: commit.time().seconds(),
+        subject: commit.summary()?.unwrap_or_default().to_string(),
+    })
+}
+
+/// Build a worktree entry from the HEAD of `repo`
+fn describe_head(repo: &Repository, path: PathBuf) -> Worktree {
+    let branch = head_branch(repo);
+    Worktree {
+        path,
+        head: repo.head().ok().and_then(|head| head.target()).map(|oid| oid.to_string()),
+        detached: branch.is_none(),
+        branch,
+        ..Default::default()
+    }
+}
+
+/// Build a worktree entry from the HEAD file in a worktree's admin directory
+fn read_head_file(main_repo: &Repository, name: &str, path: PathBuf) -> Worktree {
+    let head_file = main_repo.path().join("worktrees").join(name).join("HEAD");
+    let head = std::fs::read_to_string(head_file).unwrap_or_default();
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(target) => Worktree {
+            path,
+            head: main_repo.refname_to_id(target).ok().map(|oid| oid.to_string()),
+            branch: Some(target.strip_prefix("refs/heads/").unwrap_or(target).to_string()),
+            ..Default::default()
+        },
+        None => Worktree {
+            path,
+            head: (!head.is_empty()).then(|| head.to_string()),
+            detached: true,
+            ..Default::default()
+        },
+    }
+}
+
+/// Short name of the branch HEAD points to, including an unborn branch
+fn head_branch(repo: &Repository) -> Option<String> {
+    let head = repo.find_reference("HEAD").ok()?;
+    let target = head.symbolic_target().ok()??;
+    Some(target.strip_prefix("refs/heads/").unwrap_or(target).to_string())
+}
+
+/// Drop the trailing separator libgit2 leaves on directory paths
+fn normalize(path: &Path) -> PathBuf {
+    path.components().collect()
+}
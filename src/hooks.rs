@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use crate::config::{Config, HookCommand, OnFailure};
+use crate::worktrees::Worktree;
+
+/// Points in a worktree's life where configured commands run
+#[derive(Clone, Copy)]
+pub enum Hook {
+    /// After `wt add` creates a worktree and copies files into it
+    PostCreate,
+    /// Before a worktree is removed, while it still exists
+    PreRemove,
+    /// After a worktree is removed; runs in the main worktree
+    PostRemove,
+    /// After `wt merge` merges a branch, before its worktree is removed
+    PostMerge,
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Hook::PostCreate => "post-create",
+            Hook::PreRemove => "pre-remove",
+            Hook::PostRemove => "post-remove",
+            Hook::PostMerge => "post-merge",
+        }
+    }
+
+    fn commands(self, config: &Config) -> &[HookCommand] {
+        match self {
+            Hook::PostCreate => &config.hooks.post_create,
+            Hook::PreRemove => &config.hooks.pre_remove,
+            Hook::PostRemove => &config.hooks.post_remove,
+            Hook::PostMerge => &config.hooks.post_merge,
+        }
+    }
+}
+
+/// Run each command configured for `hook` through the shell, inside `wt`
+/// if it still exists and the main worktree otherwise. The worktree is
+/// described to the commands through `WT_*` environment variables.
+pub fn run(config: &Config, hook: Hook, wt: &Worktree, main_worktree_path: &Path) -> Result<()> {
+    let dir = if wt.path.is_dir() { &wt.path } else { main_worktree_path };
+
+    for command in hook.commands(config) {
+        eprintln!("Running {} hook: {}", hook.name(), command.run);
+        // Hooks must not write to stdout, which older shell wrappers evaluate
+        let status = Command::new("sh")
+            .current_dir(dir)
+            .args(["-c", &command.run])
+            .env("WT_HOOK", hook.name())
+            .env("WT_NAME", wt.name())
+            .env("WT_PATH", &wt.path)
+            .env("WT_BRANCH", wt.branch.as_deref().unwrap_or_default())
+            .env("WT_MAIN_PATH", main_worktree_path)
+            .stdout(Stdio::from(std::io::stderr()))
+            .status()
+            .with_context(|| format!("Failed to run {} hook: {}", hook.name(), command.run))?;
+
+        if status.success() {
+            continue;
+        }
+        match command.on_failure.unwrap_or(config.hooks.on_failure) {
+            OnFailure::Abort => bail!("{} hook failed ({}): {}", hook.name(), status, command.run),
+            OnFailure::Warn => {
+                eprintln!("Warning: {} hook failed ({}): {}", hook.name(), status, command.run)
+            }
+            OnFailure::Ignore => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! `wt info` and `wt describe`: everything known about a worktree, and a
+//! note to go with it.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use crate::config::Config;
+use crate::list::{self, Repo};
+use crate::meta::Meta;
+use crate::utils;
+use crate::worktrees::{self, Worktree};
+
+/// The worktree `name` refers to, or the one we're in
+fn resolve(config: &Config, name: Option<&str>) -> Result<Worktree> {
+    match name {
+        Some(name) => worktrees::get(config, name),
+        None => worktrees::current(),
+    }
+}
+
+/// Set the description of worktree `name`, or clear it if `text` is empty
+pub fn describe(name: &str, text: &str) -> Result<()> {
+    let config = Config::load()?;
+    let wt = worktrees::get(&config, name)?;
+    if wt.path == utils::get_main_worktree_path()? {
+        bail!("Cannot describe the main worktree");
+    }
+
+    let mut meta = Meta::load(wt.name())?;
+    let text = text.trim();
+    meta.description = (!text.is_empty()).then(|| text.to_string());
+    meta.save(wt.name())?;
+    match &meta.description {
+        Some(_) => eprintln!("Described worktree {}", wt.name()),
+        None => eprintln!("Cleared the description of worktree {}", wt.name()),
+    }
+    Ok(())
+}
+
+pub fn execute(name: Option<&str>, json: bool) -> Result<()> {
+    let config = Config::load()?;
+    let status = Repo::load()?.status(resolve(&config, name)?);
+
+    if json {
+        let json = serde_json::to_string_pretty(&status).context("Failed to serialize the worktree")?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    let meta = &status.meta;
+    let mut rows = vec![
+        ("name", status.name.clone()),
+        ("path", status.path.display().to_string()),
+        (
+            "branch",
+            status.branch.clone().unwrap_or_else(|| {
+                if status.bare { "(bare)" } else { "(detached)" }.to_string()
+            }),
+        ),
+    ];
+    if let Some(description) = &meta.description {
+        rows.push(("description", description.clone()));
+    }
+    if let Some(created) = meta.created.and_then(|created| DateTime::from_timestamp(created, 0)) {
+        let local = created.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+        rows.push(("created", format!("{} ({})", local, list::format_age(created.timestamp()))));
+    }
+    if let Some(owner) = &meta.owner {
+        rows.push(("owner", owner.clone()));
+    }
+    if let Some(base) = &meta.base {
+        rows.push(("base", base.clone()));
+    }
+    if let Some(issue) = &meta.issue {
+        let url = issue.url.as_ref().map(|url| format!(" ({})", url)).unwrap_or_default();
+        rows.push(("issue", format!("{}: {}{}", issue.id, issue.title, url)));
+    }
+    if let Some(pr) = &meta.pull_request {
+        let url = pr.url.as_ref().map(|url| format!(" ({})", url)).unwrap_or_default();
+        rows.push(("pull request", format!("{}{}", pr.reference, url)));
+    }
+    if let Some(commit) = &status.last_commit {
+        rows.push(("last commit", format!("{} {} ({})", commit.hash, commit.subject, commit.age)));
+    }
+    if let Some(upstream) = &status.upstream {
+        rows.push(("upstream", format!("{} {}", upstream.refname, list::format_divergence(upstream))));
+    }
+    if let Some(main) = &status.main {
+        rows.push(("main", format!("{} {}", main.refname, list::format_divergence(main))));
+    }
+    rows.push(("changes", list::format_changes(&status)));
+
+    let key_width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, value) in &rows {
+        println!("{:key_width$}  {}", key, value);
+    }
+
+    Ok(())
+}
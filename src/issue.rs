@@ -0,0 +1,61 @@
+//! Looking up the issues `wt add --issue` creates worktrees for.
+//!
+//! Numbered issues come from the forge. The `issue.cache` file answers when
+//! the forge can't, such as offline or for tickets kept elsewhere:
+//!
+//! ```toml
+//! 482 = "Crash when saving"
+//! PROJ-12 = { title = "Export to CSV", url = "https://tracker.example.com/PROJ-12" }
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use crate::config::Config;
+use crate::forge::{self, Issue};
+
+/// A ticket in the cache: just its title, or a table with its link too
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "a title, or a table like { title = \"...\", url = \"...\" }")]
+enum CacheEntry {
+    Title(String),
+    Table { title: String, url: Option<String> },
+}
+
+/// Find issue `id`, e.g. `482`, `#482` or `PROJ-12`
+pub fn find(config: &Config, main_worktree_path: &Path, id: &str) -> Result<Issue> {
+    let id = id.trim_start_matches('#');
+    if let Ok(number) = id.parse::<u64>() {
+        if let Some(forge) = forge::detect(config, main_worktree_path)? {
+            match forge.issue(number) {
+                Ok(issue) => return Ok(issue),
+                Err(err) if config.issue_cache.is_some() => {
+                    eprintln!("Warning: Could not look up issue #{} on {}: {:#}", number, forge.name(), err);
+                }
+                Err(err) => return Err(err.context(format!("Failed to look up issue #{}", number))),
+            }
+        }
+    }
+
+    let Some(cache) = &config.issue_cache else {
+        bail!("Can't look up issue {}: no forge detected for origin and no issue.cache configured", id);
+    };
+    // A relative cache path is shared through the main worktree
+    let path = main_worktree_path.join(cache);
+    let contents = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut tickets: BTreeMap<String, CacheEntry> =
+        toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+    let (title, url) = match tickets.remove(id) {
+        Some(CacheEntry::Title(title)) => (title, None),
+        Some(CacheEntry::Table { title, url }) => (title, url),
+        None => bail!("Issue {} is not in {}", id, path.display()),
+    };
+    eprintln!("Found issue {} in {}", id, path.display());
+    Ok(Issue {
+        id: id.to_string(),
+        title,
+        url,
+    })
+}
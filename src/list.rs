@@ -1,19 +1,344 @@
 use anyhow::{Context, Result};
-use std::process::Command;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use crate::git::{self, GitBackend};
+use crate::meta::Meta;
+use crate::utils;
+use crate::worktrees::{self, Worktree};
 
-pub fn execute() -> Result<()> {
-    let output = Command::new("git")
-        .args(["worktree", "list"])
-        .output()
-        .context("Failed to execute git worktree list")?;
+/// How `wt list` prints its results
+#[derive(Clone, Copy)]
+pub enum Format {
+    /// Aligned table for humans
+    Table,
+    /// JSON array of worktrees
+    Json,
+    /// One tab-separated line per worktree, stable for scripts
+    Porcelain,
+}
+
+#[derive(Default, Serialize)]
+pub struct WorktreeStatus {
+    pub name: String,
+    pub branch: Option<String>,
+    pub path: PathBuf,
+    pub head: Option<String>,
+    pub current: bool,
+    pub detached: bool,
+    pub bare: bool,
+    pub locked: Option<String>,
+    pub prunable: Option<String>,
+    pub upstream: Option<Divergence>,
+    pub main: Option<Divergence>,
+    pub dirty: Option<u32>,
+    pub untracked: Option<u32>,
+    pub last_commit: Option<LastCommit>,
+    /// What `wt` recorded about the worktree, see `wt info`
+    pub meta: Meta,
+}
+
+/// How far a worktree's HEAD is ahead of and behind another ref
+#[derive(Serialize)]
+pub struct Divergence {
+    #[serde(rename = "ref")]
+    pub refname: String,
+    pub ahead: u32,
+    pub behind: u32,
+}
+
+#[derive(Serialize)]
+pub struct LastCommit {
+    pub hash: String,
+    pub timestamp: i64,
+    pub age: String,
+    pub subject: String,
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("Failed to list worktrees: {}", stderr);
+/// What the status of each worktree is measured against
+pub struct Repo {
+    main_worktree_path: PathBuf,
+    main_branch: Option<String>,
+    current_path: Option<PathBuf>,
+    backend: Box<dyn GitBackend>,
+}
+
+impl Repo {
+    pub fn load() -> Result<Repo> {
+        let main_worktree_path = utils::get_main_worktree_path()?;
+        Ok(Repo {
+            main_branch: utils::get_main_branch_name(&main_worktree_path).ok(),
+            current_path: worktrees::current().ok().map(|wt| wt.path),
+            backend: git::backend(),
+            main_worktree_path,
+        })
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    print!("{}", stdout);
+    pub fn status(&self, wt: Worktree) -> WorktreeStatus {
+        let backend = self.backend.as_ref();
+        let is_main = wt.path == self.main_worktree_path;
+        let (upstream, dirty, untracked) = match backend.status(&wt.path) {
+            Ok(status) => (status.upstream.map(Divergence::from), Some(status.dirty), Some(status.untracked.len() as u32)),
+            Err(_) => (None, None, None),
+        };
+        let main = match (&self.main_branch, is_main) {
+            (Some(main_branch), false) => backend.divergence(&wt.path, main_branch).ok().map(Divergence::from),
+            _ => None,
+        };
+        // The main worktree's directory name could collide with a worktree's
+        let meta = match is_main {
+            true => Meta::default(),
+            false => Meta::load(wt.name()).unwrap_or_default(),
+        };
+
+        WorktreeStatus {
+            current: self.current_path.as_ref() == Some(&wt.path),
+            name: wt.name().to_string(),
+            branch: wt.branch,
+            head: wt.head,
+            detached: wt.detached,
+            bare: wt.bare,
+            locked: wt.locked,
+            prunable: wt.prunable,
+            upstream,
+            main,
+            dirty,
+            untracked,
+            last_commit: get_last_commit(backend, &wt.path),
+            meta,
+            path: wt.path,
+        }
+    }
+}
+
+pub fn execute(format: Format) -> Result<()> {
+    let repo = Repo::load()?;
+    let statuses: Vec<WorktreeStatus> = worktrees::list()?.into_iter().map(|wt| repo.status(wt)).collect();
+
+    match format {
+        Format::Table => print_table(&statuses),
+        Format::Json => {
+            let json = serde_json::to_string_pretty(&statuses)
+                .context("Failed to serialize worktrees")?;
+            println!("{}", json);
+        }
+        Format::Porcelain => print_porcelain(&statuses),
+    }
 
     Ok(())
 }
+
+fn print_table(statuses: &[WorktreeStatus]) {
+    let mut headers = vec!["", "NAME", "BRANCH", "PATH", "UPSTREAM", "MAIN", "CHANGES", "LAST COMMIT"];
+    // Only worth a column once something has been described
+    let described = statuses.iter().any(|status| status.meta.description.is_some());
+    if described {
+        headers.push("DESCRIPTION");
+    }
+    let rows: Vec<Vec<String>> = statuses
+        .iter()
+        .map(|status| {
+            let mut row = vec![
+                if status.current { "*" } else { "" }.to_string(),
+                status.name.clone(),
+                status.branch.clone().unwrap_or_else(|| {
+                    if status.bare { "(bare)" } else { "(detached)" }.to_string()
+                }),
+                status.path.display().to_string(),
+                status.upstream.as_ref().map(format_divergence).unwrap_or_default(),
+                status.main.as_ref().map(format_divergence).unwrap_or_default(),
+                format_changes(status),
+                status
+                    .last_commit
+                    .as_ref()
+                    .map(|commit| format!("{}  {}", commit.age, commit.subject))
+                    .unwrap_or_default(),
+            ];
+            if described {
+                row.push(status.meta.description.clone().unwrap_or_default());
+            }
+            row
+        })
+        .collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |cells: &[String]| {
+        let last = cells.len() - 1;
+        let line: Vec<String> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if i == last {
+                    cell.clone()
+                } else {
+                    format!("{:width$}", cell, width = widths[i])
+                }
+            })
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+
+    print_row(&headers.iter().map(|header| header.to_string()).collect::<Vec<_>>());
+    for row in &rows {
+        print_row(row);
+    }
+}
+
+/// Tab-separated fields: current, name, branch, path, upstream, upstream ahead,
+/// upstream behind, main ahead, main behind, dirty, untracked, commit hash,
+/// commit timestamp, commit subject, description. Unknown values are left empty.
+fn print_porcelain(statuses: &[WorktreeStatus]) {
+    for status in statuses {
+        println!("{}", porcelain_line(status));
+    }
+}
+
+fn porcelain_line(status: &WorktreeStatus) -> String {
+    fn opt<T: ToString>(value: Option<T>) -> String {
+        value.map(|v| v.to_string()).unwrap_or_default()
+    }
+    fn text<T: AsRef<str>>(value: Option<T>) -> String {
+        value.map(|v| escape(v.as_ref())).unwrap_or_default()
+    }
+
+    let fields = [
+        if status.current { "1" } else { "0" }.to_string(),
+        escape(&status.name),
+        text(status.branch.as_ref()),
+        escape(&status.path.to_string_lossy()),
+        text(status.upstream.as_ref().map(|d| &d.refname)),
+        opt(status.upstream.as_ref().map(|d| d.ahead)),
+        opt(status.upstream.as_ref().map(|d| d.behind)),
+        opt(status.main.as_ref().map(|d| d.ahead)),
+        opt(status.main.as_ref().map(|d| d.behind)),
+        opt(status.dirty),
+        opt(status.untracked),
+        opt(status.last_commit.as_ref().map(|c| &c.hash)),
+        opt(status.last_commit.as_ref().map(|c| c.timestamp)),
+        text(status.last_commit.as_ref().map(|c| &c.subject)),
+        text(status.meta.description.as_ref()),
+    ];
+    fields.join("\t")
+}
+
+/// Escape backslashes, tabs and line breaks as `\\`, `\t`, `\n` and `\r`,
+/// so a field can't spill into the next one or the next line
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn format_divergence(divergence: &Divergence) -> String {
+    match (divergence.ahead, divergence.behind) {
+        (0, 0) => "=".to_string(),
+        (ahead, 0) => format!("↑{}", ahead),
+        (0, behind) => format!("↓{}", behind),
+        (ahead, behind) => format!("↑{} ↓{}", ahead, behind),
+    }
+}
+
+pub fn format_changes(status: &WorktreeStatus) -> String {
+    if status.prunable.is_some() {
+        return "missing".to_string();
+    }
+    let changes = match (status.dirty, status.untracked) {
+        (Some(0), Some(0)) => "clean".to_string(),
+        (Some(dirty), Some(untracked)) => {
+            let mut parts = Vec::new();
+            if dirty > 0 {
+                parts.push(format!("{} modified", dirty));
+            }
+            if untracked > 0 {
+                parts.push(format!("{} untracked", untracked));
+            }
+            parts.join(", ")
+        }
+        _ => String::new(),
+    };
+    if status.locked.is_some() {
+        format!("{} (locked)", changes).trim_start().to_string()
+    } else {
+        changes
+    }
+}
+
+/// Describe how long ago `timestamp` was, e.g. "3 days ago"
+pub fn format_age(timestamp: i64) -> String {
+    let seconds = (chrono::Utc::now().timestamp() - timestamp).max(0);
+    let (count, unit) = match seconds {
+        s if s < 60 => (s, "second"),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 86400 * 30 => (s / 86400, "day"),
+        s if s < 86400 * 365 => (s / (86400 * 30), "month"),
+        s => (s / (86400 * 365), "year"),
+    };
+    format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
+
+impl From<git::Divergence> for Divergence {
+    fn from(divergence: git::Divergence) -> Self {
+        Divergence {
+            refname: divergence.refname,
+            ahead: divergence.ahead,
+            behind: divergence.behind,
+        }
+    }
+}
+
+fn get_last_commit(backend: &dyn GitBackend, worktree_path: &Path) -> Option<LastCommit> {
+    let commit = backend.commit(worktree_path, "HEAD").ok()?;
+    Some(LastCommit {
+        hash: commit.short_hash,
+        timestamp: commit.timestamp,
+        age: format_age(commit.timestamp),
+        subject: commit.subject,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn porcelain_escapes_free_text() {
+        let status = WorktreeStatus {
+            name: "tab\there".to_string(),
+            branch: Some("feat".to_string()),
+            path: PathBuf::from("/tmp/tab\there"),
+            last_commit: Some(LastCommit {
+                hash: "abc1234".to_string(),
+                timestamp: 1,
+                age: String::new(),
+                subject: "Fix\tthe \\ thing".to_string(),
+            }),
+            meta: Meta {
+                description: Some("two\nlines".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let line = porcelain_line(&status);
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(fields.len(), 15);
+        assert_eq!(fields[1], "tab\\there");
+        assert_eq!(fields[3], "/tmp/tab\\there");
+        assert_eq!(fields[13], "Fix\\tthe \\\\ thing");
+        assert_eq!(fields[14], "two\\nlines");
+    }
+}
@@ -1,13 +1,30 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::engine::ArgValueCandidates;
+use clap_complete::env::CompleteEnv;
 use anyhow::Result;
 
 mod add;
 mod checkout;
+mod completions;
+mod config;
+mod copy;
+mod forge;
+mod git;
+mod hooks;
+mod info;
+mod issue;
 mod list;
 mod master;
 mod merge;
+mod meta;
+mod pr;
+mod prune;
 mod remove;
+mod seed;
+mod shell;
+mod template;
 mod utils;
+mod worktrees;
 
 #[derive(Parser)]
 #[command(name = "worktree")]
@@ -22,45 +39,260 @@ enum Commands {
     /// Add a new git worktree
     #[command(visible_aliases = ["new", "init"])]
     Add {
-        /// Name of the branch/worktree
-        name: String,
+        /// Name of the branch/worktree, or the remote branch with --track.
+        /// With --pr or --issue, overrides the branch name picked for it
+        #[arg(required_unless_present_any = ["pr", "issue"])]
+        name: Option<String>,
+        /// Use an existing local branch, or the branch of the same name on origin
+        #[arg(long)]
+        existing: bool,
+        /// Create a local branch tracking a remote branch, e.g. origin/feature
+        #[arg(long, conflicts_with = "existing")]
+        track: bool,
+        /// Ref to start the new branch from [default: base from config]
+        #[arg(
+            long,
+            value_name = "REF",
+            conflicts_with_all = ["existing", "track"],
+            add = ArgValueCandidates::new(completions::refs)
+        )]
+        from: Option<String>,
+        /// Check out a pull request, by number or URL, including from forks
+        #[arg(long, value_name = "PR", conflicts_with_all = ["existing", "track", "from"])]
+        pr: Option<String>,
+        /// Create a branch for an issue, named by issue.branch from its title
+        #[arg(long, value_name = "ID", conflicts_with_all = ["existing", "track", "pr"])]
+        issue: Option<String>,
+        /// Clone the build directories listed in `seed` from the main worktree
+        #[arg(long)]
+        seed: bool,
+    },
+    /// Add a worktree to review a pull request, like `add --pr`
+    Review {
+        /// Pull request number or URL
+        pr: String,
+        /// Branch name [default: the PR's head branch, or pr-<number>]
+        name: Option<String>,
+        /// Clone the build directories listed in `seed` from the main worktree
+        #[arg(long)]
+        seed: bool,
     },
     /// Change directory to a worktree by name
     #[command(visible_aliases = ["co"])]
     Checkout {
-        /// Name of the worktree (directory name or branch name)
-        name: String,
+        /// Name of the worktree (directory or branch name, or part of one);
+        /// pick one interactively if omitted
+        #[arg(add = ArgValueCandidates::new(completions::worktree_names))]
+        name: Option<String>,
     },
     /// Change directory to the master git repository
     Master,
+    /// Note what a worktree is for, shown by `wt list` and `wt info`
+    Describe {
+        /// Name of the worktree
+        #[arg(add = ArgValueCandidates::new(completions::worktree_names))]
+        name: String,
+        /// The description; an empty one clears it
+        text: String,
+    },
+    /// Print everything known about a worktree
+    Info {
+        /// Name of the worktree [default: the current one]
+        #[arg(add = ArgValueCandidates::new(completions::worktree_names))]
+        name: Option<String>,
+        /// Print the worktree as JSON, like one entry of `wt list --json`
+        #[arg(long)]
+        json: bool,
+    },
     /// List all git worktrees
     #[command(visible_aliases = ["ls"])]
-    List,
+    List {
+        /// Print worktrees as JSON
+        #[arg(long, conflicts_with = "porcelain")]
+        json: bool,
+        /// Print one tab-separated line per worktree, stable for scripts
+        #[arg(long)]
+        porcelain: bool,
+    },
     /// Remove a git worktree
     #[command(visible_aliases = ["rm"])]
     Remove {
         /// Name of the worktree to remove, or '.' for current
+        #[arg(add = ArgValueCandidates::new(completions::worktree_names))]
         name: String,
+        /// Remove even if the worktree has uncommitted, untracked, unpushed or stashed work
+        #[arg(short, long)]
+        force: bool,
+        /// Also delete the branch, if it is merged into the main branch or pushed
+        #[arg(short = 'd', long)]
+        delete_branch: bool,
     },
     /// Merge the PR for the current worktree and clean up
     Merge {
         /// Name of the worktree to merge (required if on main, optional if inside a worktree)
+        #[arg(add = ArgValueCandidates::new(completions::worktree_names))]
         name: Option<String>,
-        /// Merge strategy: squash, merge, or rebase
-        #[arg(short, long, default_value = "squash")]
-        strategy: String,
+        /// Merge strategy [default: merge.strategy from config]
+        #[arg(short, long, value_parser = ["squash", "merge", "rebase"])]
+        strategy: Option<String>,
+        /// Commit message for a local squash merge, instead of one built from the branch's commits
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Edit the squash commit message before committing
+        #[arg(short, long)]
+        edit: bool,
+        /// Skip the merge.verify commands
+        #[arg(long)]
+        no_verify: bool,
+        /// Enable auto-merge when the PR is still waiting on checks or reviews
+        #[arg(long, conflicts_with = "resume")]
+        auto: bool,
+        /// Remove the worktree even if it has untracked or modified files left in it
+        #[arg(short, long)]
+        force: bool,
+        /// Finish a merge that stopped on conflicts or a failing cleanup step
+        #[arg(long = "continue", conflicts_with_all = ["name", "strategy", "abort"])]
+        resume: bool,
+        /// Undo a merge that stopped on conflicts
+        #[arg(long, conflicts_with_all = ["name", "strategy", "message", "edit", "no_verify", "auto", "force"])]
+        abort: bool,
+    },
+    /// Push a worktree's branch and open a pull request for it, or print the open one
+    Pr {
+        /// Name of the worktree (required if on main, optional if inside a worktree)
+        #[arg(add = ArgValueCandidates::new(completions::worktree_names))]
+        name: Option<String>,
+        /// Title of the pull request [default: pr.title from config, or the first commit's subject]
+        #[arg(short, long)]
+        title: Option<String>,
+        /// Body of the pull request [default: pr.body from config, or the branch's commits]
+        #[arg(short, long)]
+        body: Option<String>,
+        /// Branch to merge into [default: merge.base from config, or the main branch]
+        #[arg(long, value_name = "BRANCH")]
+        base: Option<String>,
+        /// Open the pull request as a draft
+        #[arg(short, long)]
+        draft: bool,
+        /// Request a review from a user; repeat or separate with commas
+        #[arg(short, long = "reviewer", value_name = "USER", value_delimiter = ',')]
+        reviewers: Vec<String>,
+        /// Add a label; repeat or separate with commas
+        #[arg(short, long = "label", value_name = "LABEL", value_delimiter = ',')]
+        labels: Vec<String>,
+        /// Open the pull request in the browser
+        #[arg(short, long)]
+        web: bool,
+    },
+    /// Remove worktrees whose branches are merged or whose upstream is gone
+    Prune {
+        /// Only branches merged into the main branch, including squash and rebase merges
+        #[arg(long)]
+        merged: bool,
+        /// Only branches whose upstream was deleted from the remote
+        #[arg(long)]
+        gone: bool,
+        /// Remove the worktrees and branches instead of only listing them
+        #[arg(short, long)]
+        yes: bool,
+        /// Remove even if a worktree has uncommitted, untracked or stashed work
+        #[arg(short, long)]
+        force: bool,
+        /// Don't fetch first to find upstreams deleted on the remote
+        #[arg(long)]
+        no_fetch: bool,
+    },
+    /// Print the shell function that lets `wt` change directory, e.g.
+    /// `eval "$(worktree shell-init bash)"`
+    ShellInit {
+        shell: shell::Shell,
+    },
+    /// Print a script that sets up tab completion, e.g.
+    /// `source <(worktree completions bash)`
+    Completions {
+        #[arg(value_parser = completions::SHELLS.to_vec())]
+        shell: String,
+    },
+    /// Inspect the worktree configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
     },
 }
 
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the resolved configuration and where each value came from
+    Show,
+}
+
 fn main() -> Result<()> {
+    // Answer completion requests from the script printed by `wt completions`
+    CompleteEnv::with_factory(Cli::command).var(completions::COMPLETE_VAR).complete();
+
     let cli = Cli::parse();
 
     match cli.command {
-        Some(Commands::Add { name }) => add::execute(&name),
-        Some(Commands::Checkout { name }) => checkout::execute(&name),
+        Some(Commands::Add { name, existing, track, from, pr, issue, seed }) => {
+            let source = if let Some(spec) = pr {
+                add::BranchSource::PullRequest { spec }
+            } else if let Some(id) = issue {
+                add::BranchSource::Issue { id, from }
+            } else if existing {
+                add::BranchSource::Existing
+            } else if track {
+                add::BranchSource::Track
+            } else {
+                add::BranchSource::New { from }
+            };
+            add::execute(name.as_deref(), source, seed)
+        }
+        Some(Commands::Review { pr, name, seed }) => {
+            add::execute(name.as_deref(), add::BranchSource::PullRequest { spec: pr }, seed)
+        }
+        Some(Commands::Checkout { name }) => checkout::execute(name.as_deref()),
         Some(Commands::Master) => master::execute(),
-        Some(Commands::List) | None => list::execute(),
-        Some(Commands::Remove { name }) => remove::execute(&name),
-        Some(Commands::Merge { name, strategy }) => merge::execute(name.as_deref(), &strategy),
+        Some(Commands::Describe { name, text }) => info::describe(&name, &text),
+        Some(Commands::Info { name, json }) => info::execute(name.as_deref(), json),
+        Some(Commands::List { json, porcelain }) => {
+            let format = if json {
+                list::Format::Json
+            } else if porcelain {
+                list::Format::Porcelain
+            } else {
+                list::Format::Table
+            };
+            list::execute(format)
+        }
+        None => list::execute(list::Format::Table),
+        Some(Commands::Remove { name, force, delete_branch }) => {
+            remove::execute(&name, force, delete_branch)
+        }
+        Some(Commands::Merge { name, strategy, message, edit, no_verify, auto, force, resume, abort }) => {
+            let resume = if resume {
+                Some(merge::Resume::Continue)
+            } else if abort {
+                Some(merge::Resume::Abort)
+            } else {
+                None
+            };
+            let options = merge::Options { strategy, message, edit, no_verify, auto, force };
+            merge::execute(name.as_deref(), options, resume)
+        }
+        Some(Commands::Pr { name, title, body, base, draft, reviewers, labels, web }) => {
+            let options = pr::Options { title, body, base, draft, reviewers, labels, web };
+            pr::execute(name.as_deref(), options)
+        }
+        Some(Commands::Prune { merged, gone, yes, force, no_fetch }) => {
+            // Without a filter, look for both kinds of stale worktree
+            let filter = prune::Filter {
+                merged: merged || !gone,
+                gone: gone || !merged,
+            };
+            prune::execute(filter, yes, force, !no_fetch)
+        }
+        Some(Commands::ShellInit { shell }) => shell::init(shell),
+        Some(Commands::Completions { shell }) => completions::execute(&shell),
+        Some(Commands::Config { command: ConfigCommands::Show }) => config::show(),
     }
 }
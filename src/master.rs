@@ -1,4 +1,5 @@
 use anyhow::Result;
+use crate::shell;
 use crate::utils;
 
 pub fn execute() -> Result<()> {
@@ -6,8 +7,8 @@ pub fn execute() -> Result<()> {
 
     eprintln!("Changing to main worktree: {}", main_worktree_path.display());
 
-    // Print the cd command for the shell to execute
-    utils::print_cd_command(&main_worktree_path);
+    // Have the shell wrapper change directory
+    shell::cd(&main_worktree_path)?;
 
     Ok(())
 }
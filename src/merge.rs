@@ -1,244 +1,780 @@
+//! `wt merge`: landing a worktree's branch and cleaning the worktree up.
+//!
+//! Progress is recorded in `<git-common-dir>/worktree-merge.json`, so a merge
+//! that stops on conflicts or a failing hook can be finished with
+//! `wt merge --continue` or undone with `wt merge --abort`.
+
 use anyhow::{bail, Context, Result};
-use std::process::Command;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use crate::config::Config;
+use crate::forge::{self, Blocker};
+use crate::git;
+use crate::hooks::{self, Hook};
+use crate::meta::{Meta, PullRequestLink};
+use crate::remove;
+use crate::shell;
+use crate::template;
 use crate::utils;
+use crate::worktrees::{self, Worktree};
+
+/// How `wt merge` lands a branch
+pub struct Options {
+    /// Defaults to `merge.strategy`
+    pub strategy: Option<String>,
+    /// Commit message for a local squash merge, instead of the generated one
+    pub message: Option<String>,
+    /// Open the editor on the squash commit message
+    pub edit: bool,
+    /// Skip the `merge.verify` commands
+    pub no_verify: bool,
+    /// Turn on auto-merge instead of refusing a PR that is waiting on checks or reviews
+    pub auto: bool,
+    /// Remove the worktree even if it has untracked or modified files left in it
+    pub force: bool,
+}
 
-/// Get the branch name checked out in a worktree
-fn get_worktree_branch(worktree_path: &std::path::Path) -> Result<String> {
-    let output = Command::new("git")
-        .current_dir(worktree_path)
-        .args(["rev-parse", "--abbrev-ref", "HEAD"])
-        .output()
-        .context("Failed to get branch name")?;
+/// Picking up a merge that stopped part way
+#[derive(Clone, Copy)]
+pub enum Resume {
+    Continue,
+    Abort,
+}
 
-    if !output.status.success() {
-        bail!("Failed to determine branch name for worktree");
+/// How far a merge got, in order
+#[derive(Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Step {
+    /// Rebasing the branch onto base stopped on conflicts in its worktree
+    Rebasing,
+    /// Merging into base stopped on conflicts in the merge directory
+    Merging,
+    /// The branch is in base; the post-merge hook is next
+    Merged,
+    /// Removing the worktree is next
+    HookRun,
+    /// Deleting the local branch is next
+    WorktreeRemoved,
+    /// Deleting the remote branch is next
+    BranchDeleted,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct MergeState {
+    branch: String,
+    worktree: PathBuf,
+    strategy: String,
+    /// Branch a local merge goes into
+    base: Option<String>,
+    /// Where a local merge is made: the worktree base is checked out in, or a temporary one
+    merge_dir: Option<PathBuf>,
+    /// The branch's commit before it was rebased, to put it back on abort
+    #[serde(default)]
+    rebased_from: Option<String>,
+    temporary: bool,
+    message: Option<String>,
+    edit: bool,
+    /// Whether to run the `merge.verify` commands
+    verify: bool,
+    /// Whether `wt merge` was run from inside the worktree being merged
+    need_cd: bool,
+    /// Remove the worktree even if files were left in it
+    #[serde(default)]
+    force: bool,
+    step: Step,
+}
+
+impl MergeState {
+    fn path() -> Result<PathBuf> {
+        Ok(git::backend().common_dir()?.join("worktree-merge.json"))
+    }
+
+    fn load() -> Result<Option<MergeState>> {
+        let path = Self::path()?;
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map(Some)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        }
     }
 
-    Ok(String::from_utf8(output.stdout)
-        .context("Invalid UTF-8 in branch name")?
-        .trim()
-        .to_string())
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn clear() -> Result<()> {
+        let path = Self::path()?;
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))
+    }
+
+    fn advance(&mut self, step: Step) -> Result<()> {
+        self.step = step;
+        self.save()
+    }
+
+    fn base(&self) -> &str {
+        self.base.as_deref().unwrap_or_default()
+    }
 }
 
-/// Get the branch name checked out in the main worktree
-fn get_main_branch_name(main_worktree_path: &std::path::Path) -> Result<String> {
+/// Run git in `dir`, failing with what it printed. Conflicts are reported on
+/// stdout, and hints about resolving them are dropped since `wt merge` has its own.
+fn run_git(dir: &Path, args: &[&str]) -> Result<()> {
     let output = Command::new("git")
-        .current_dir(main_worktree_path)
-        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .current_dir(dir)
+        .args(args)
         .output()
-        .context("Failed to get main branch name")?;
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
 
     if !output.status.success() {
-        bail!("Failed to determine main branch name");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message: Vec<&str> = stdout
+            .lines()
+            .chain(stderr.lines())
+            .filter(|line| !line.starts_with("hint:") && !line.trim().is_empty())
+            .collect();
+        bail!("{}", message.join("\n"));
     }
+    Ok(())
+}
 
-    Ok(String::from_utf8(output.stdout)
-        .context("Invalid UTF-8 in branch name")?
-        .trim()
-        .to_string())
+/// Run the `merge.verify` commands in `dir`, stopping at the first that fails
+fn verify(config: &Config, dir: &Path) -> Result<()> {
+    for command in &config.merge_verify {
+        eprintln!("Verifying: {}", command);
+        // Like hooks, keep stdout clear for older shell wrappers
+        let status = Command::new("sh")
+            .current_dir(dir)
+            .args(["-c", command])
+            .stdout(Stdio::from(io::stderr()))
+            .status()
+            .with_context(|| format!("Failed to run verify command: {}", command))?;
+        if !status.success() {
+            bail!("Verification failed ({}): {}", status, command);
+        }
+    }
+    Ok(())
 }
 
-/// Merge a branch into the main branch locally
-fn merge_locally(main_worktree_path: &std::path::Path, branch_name: &str, strategy: &str) -> Result<()> {
-    let main_branch = get_main_branch_name(main_worktree_path)?;
-    eprintln!("Merging {} into {} locally", branch_name, main_branch);
+fn ensure_clean(dir: &Path) -> Result<()> {
+    if git::backend().status(dir)?.dirty > 0 {
+        bail!("{} has uncommitted changes; commit or stash them before merging", dir.display());
+    }
+    Ok(())
+}
 
-    match strategy {
-        "squash" => {
-            let output = Command::new("git")
-                .current_dir(main_worktree_path)
-                .args(["merge", "--squash", branch_name])
-                .output()
-                .context("Failed to execute git merge --squash")?;
+fn ensure_resolved(dir: &Path) -> Result<()> {
+    let conflicts = git::backend().conflicted_files(dir)?;
+    if !conflicts.is_empty() {
+        bail!(
+            "Conflicts remain in {}:\n  {}\nResolve them and `git add` the files, then run `wt merge --continue`",
+            dir.display(),
+            conflicts.join("\n  ")
+        );
+    }
+    Ok(())
+}
 
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                bail!("Failed to squash merge: {}", stderr);
-            }
+/// The worktree base is checked out in, which must be clean to merge into
+fn base_worktree(base: &str) -> Result<Option<Worktree>> {
+    let wt = worktrees::list()?
+        .into_iter()
+        .find(|wt| wt.branch.as_deref() == Some(base));
+    if let Some(wt) = &wt {
+        ensure_clean(&wt.path)?;
+    }
+    Ok(wt)
+}
 
-            // Commit the squashed changes
-            let output = Command::new("git")
-                .current_dir(main_worktree_path)
-                .args(["commit", "-m", &format!("Squashed merge of branch '{}'", branch_name)])
-                .output()
-                .context("Failed to commit squash merge")?;
+fn temp_worktree_path() -> Result<PathBuf> {
+    Ok(git::backend().common_dir()?.join("worktree-merge"))
+}
+
+fn remove_temp_worktree(main_worktree_path: &Path, path: &Path) {
+    let path = path.to_string_lossy();
+    if let Err(err) = run_git(main_worktree_path, &["worktree", "remove", "--force", &path]) {
+        eprintln!("Warning: Failed to remove temporary worktree {}: {}", path, err);
+    }
+}
 
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                bail!("Failed to commit squash merge: {}", stderr);
+/// Merge the worktree's branch into the base branch locally. The merge happens
+/// wherever base is checked out, which must be clean, or in a temporary
+/// worktree if it isn't checked out anywhere.
+fn merge_locally(config: &Config, main_worktree_path: &Path, state: &mut MergeState) -> Result<()> {
+    let base = match &config.merge_base {
+        Some(base) => base.clone(),
+        None => utils::get_main_branch_name(main_worktree_path)?,
+    };
+    if base == state.branch {
+        bail!("Cannot merge {} into itself", state.branch);
+    }
+    if !git::backend().ref_exists(&format!("refs/heads/{}", base))? {
+        bail!("Base branch {} does not exist", base);
+    }
+    eprintln!("Merging {} into {} locally", state.branch, base);
+    state.base = Some(base.clone());
+    base_worktree(&base)?;
+
+    // Replay the branch onto base, so base only has to fast-forward
+    if state.strategy == "rebase" {
+        ensure_clean(&state.worktree)?;
+        let feature_head = git::backend().commit(&state.worktree, "HEAD")?.hash;
+        state.rebased_from = Some(feature_head.clone());
+        eprintln!("Rebasing {} onto {}", state.branch, base);
+        if let Err(err) = run_git(&state.worktree, &["rebase", &base]) {
+            return stop(main_worktree_path, state, Step::Rebasing, err);
+        }
+        // The rebased branch is what base will fast-forward to
+        if state.verify {
+            if let Err(err) = verify(config, &state.worktree) {
+                if let Err(err) = run_git(&state.worktree, &["reset", "--keep", &feature_head]) {
+                    eprintln!("Warning: Failed to undo the rebase: {}", err);
+                }
+                return Err(err.context(format!("Not merging {}, nothing was changed", state.branch)));
             }
         }
-        "merge" => {
-            let output = Command::new("git")
-                .current_dir(main_worktree_path)
-                .args(["merge", branch_name])
-                .output()
-                .context("Failed to execute git merge")?;
+    }
 
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                bail!("Failed to merge: {}", stderr);
+    land(config, main_worktree_path, state)
+}
+
+/// Bring the branch into base, once any rebase is done
+fn land(config: &Config, main_worktree_path: &Path, state: &mut MergeState) -> Result<()> {
+    let dir = match base_worktree(state.base())? {
+        Some(wt) => wt.path,
+        None => {
+            let path = temp_worktree_path()?;
+            if path.exists() {
+                remove_temp_worktree(main_worktree_path, &path);
             }
+            run_git(main_worktree_path, &["worktree", "add", "--quiet", &path.to_string_lossy(), state.base()])
+                .with_context(|| format!("Failed to check out {} in a temporary worktree", state.base()))?;
+            state.temporary = true;
+            path
         }
-        "rebase" => {
-            let output = Command::new("git")
-                .current_dir(main_worktree_path)
-                .args(["rebase", branch_name])
-                .output()
-                .context("Failed to execute git rebase")?;
+    };
+    state.merge_dir = Some(dir.clone());
+    let head = git::backend().commit(&dir, "HEAD")?.hash;
+
+    // Squash and merge leave the result uncommitted so it can be verified first
+    let result = match state.strategy.as_str() {
+        "squash" => run_git(&dir, &["merge", "--squash", &state.branch]),
+        "merge" => run_git(&dir, &["merge", "--no-commit", "--no-edit", &state.branch]),
+        "rebase" => run_git(&dir, &["merge", "--ff-only", &state.branch]),
+        _ => bail!("Invalid merge strategy: {}", state.strategy),
+    };
+    if let Err(err) = result {
+        return stop(main_worktree_path, state, Step::Merging, err);
+    }
 
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                bail!("Failed to rebase: {}", stderr);
+    // A rebased branch was already verified before base fast-forwarded to it
+    if state.verify && state.strategy != "rebase" {
+        if let Err(err) = verify(config, &dir) {
+            if let Err(err) = run_git(&dir, &["reset", "--merge", &head]) {
+                eprintln!("Warning: Failed to undo the merge: {}", err);
+            }
+            if state.temporary {
+                remove_temp_worktree(main_worktree_path, &dir);
             }
+            return Err(err.context(format!("Not merging {}, nothing was changed", state.branch)));
         }
-        _ => bail!("Invalid merge strategy: {}", strategy),
     }
 
+    if let Err(err) = commit(config, &dir, state) {
+        // Keep the uncommitted merge so the commit can be retried
+        state.advance(Step::Merging)?;
+        return Err(err.context(
+            "Run `wt merge --continue` to try committing again, or `wt merge --abort` to undo the merge",
+        ));
+    }
+
+    if state.temporary {
+        remove_temp_worktree(main_worktree_path, &dir);
+    }
     eprintln!("Local merge successful");
     Ok(())
 }
 
-pub fn execute(name: Option<&str>, strategy: &str) -> Result<()> {
-    let is_main = utils::is_main_worktree()?;
-    let main_worktree_path = utils::get_main_worktree_path()?;
+/// Commit the merge made in `dir`, if it needs a commit
+fn commit(config: &Config, dir: &Path, state: &MergeState) -> Result<()> {
+    if state.strategy == "squash" {
+        commit_squash(config, dir, state)
+    } else if run_git(dir, &["rev-parse", "--quiet", "--verify", "MERGE_HEAD"]).is_ok() {
+        // Otherwise it fast-forwarded, or the merge was committed by hand
+        run_git(dir, &["commit", "--no-edit"]).context("Failed to commit merge")
+    } else {
+        Ok(())
+    }
+}
 
-    // Determine the worktree path to merge
-    let (worktree_path, need_cd) = match (is_main, name) {
-        // On main with a name: merge that worktree
-        (true, Some(n)) => {
-            let root_dir = utils::get_root_dir()?;
-            let repo_name = utils::get_repo_name()?;
-            let path = root_dir.join(&repo_name).join(n);
-            (path, false)
-        }
-        // On main without a name: error
-        (true, None) => {
-            bail!("Must specify a worktree name when running from the main worktree.\nUsage: wt merge <worktree-name>");
+fn commit_squash(config: &Config, dir: &Path, state: &MergeState) -> Result<()> {
+    // Nothing staged means base already has all of the branch's changes
+    if run_git(dir, &["diff", "--cached", "--quiet"]).is_ok() {
+        eprintln!("Nothing to commit, {} is already merged", state.branch);
+        return Ok(());
+    }
+
+    let message = match &state.message {
+        Some(message) => message.clone(),
+        None => squash_message(config, dir, state.base(), &state.branch)?,
+    };
+    if !state.edit {
+        return run_git(dir, &["commit", "-m", &message]).context("Failed to commit squash merge");
+    }
+
+    // The editor needs the terminal; its output must stay off stdout
+    let status = Command::new("git")
+        .current_dir(dir)
+        .args(["commit", "--edit", "-m", &message])
+        .stdout(Stdio::from(io::stderr()))
+        .status()
+        .context("Failed to execute git commit")?;
+    if !status.success() {
+        bail!("Failed to commit squash merge ({})", status);
+    }
+    Ok(())
+}
+
+/// The message for squashing `branch` into `base`: a title from the first
+/// commit's subject or `merge.squash-message`, the squashed subjects, and a
+/// Co-authored-by trailer for everyone but the committer who wrote them
+fn squash_message(config: &Config, dir: &Path, base: &str, branch: &str) -> Result<String> {
+    let backend = git::backend();
+    let commits = backend
+        .messages(dir, base, branch)
+        .with_context(|| format!("Failed to list commits on {}", branch))?;
+
+    // Whoever commits the squash needs no trailer
+    let mut seen = vec![email(&backend.committer().unwrap_or_default())];
+    let mut subjects = Vec::new();
+    let mut co_authors = Vec::new();
+    for commit in commits {
+        subjects.push(commit.subject);
+        for person in std::iter::once(commit.author).chain(commit.co_authors) {
+            if !seen.contains(&email(&person)) {
+                seen.push(email(&person));
+                co_authors.push(person);
+            }
         }
-        // In a worktree without a name: merge current
-        (false, None) => {
-            let path = utils::get_current_dir()?;
-            (path, true)
+    }
+
+    let first = subjects.first().cloned().unwrap_or_else(|| format!("Squashed merge of branch '{}'", branch));
+    let mut message = match &config.merge_squash_message {
+        Some(title) => template::squash_title(title, branch, base, &first, subjects.len())?,
+        None => first,
+    };
+    if subjects.len() > 1 {
+        message.push('\n');
+        for subject in &subjects {
+            message.push_str(&format!("\n* {}", subject));
         }
-        // In a worktree with a name: merge that worktree (not current)
-        (false, Some(n)) => {
-            let root_dir = utils::get_root_dir()?;
-            let repo_name = utils::get_repo_name()?;
-            let path = root_dir.join(&repo_name).join(n);
-            (path, false)
+    }
+    if !co_authors.is_empty() {
+        message.push('\n');
+        for co_author in &co_authors {
+            message.push_str(&format!("\nCo-authored-by: {}", co_author));
         }
+    }
+    Ok(message)
+}
+
+/// The lowercased email in an identity like `Name <email> ...`
+fn email(ident: &str) -> String {
+    let start = ident.find('<').map_or(0, |i| i + 1);
+    let end = ident[start..].find('>').map_or(ident.len(), |i| start + i);
+    ident[start..end].to_lowercase()
+}
+
+/// Leave a merge that stopped on conflicts for `--continue` or `--abort`, or
+/// undo it if it failed for another reason
+fn stop(main_worktree_path: &Path, state: &mut MergeState, step: Step, err: anyhow::Error) -> Result<()> {
+    let (action, dir) = match step {
+        Step::Rebasing => (format!("Rebasing {} onto {}", state.branch, state.base()), state.worktree.clone()),
+        _ => (
+            format!("Merging {} into {}", state.branch, state.base()),
+            state.merge_dir.clone().unwrap_or_default(),
+        ),
     };
 
-    // Verify the worktree exists
-    if !worktree_path.exists() {
-        bail!("Worktree not found: {}", worktree_path.display());
+    let conflicts = git::backend().conflicted_files(&dir)?;
+    if conflicts.is_empty() {
+        undo(main_worktree_path, state, step);
+        bail!("{} failed, nothing was changed:\n{}", action, err);
     }
 
-    // Get the branch name before we do anything (need it for cleanup later)
-    let branch_name = get_worktree_branch(&worktree_path)?;
-
-    eprintln!("Merging and cleaning up worktree: {}", worktree_path.display());
-    eprintln!("Branch: {}", branch_name);
+    state.advance(step)?;
+    bail!(
+        "{} stopped on conflicts in {}:\n  {}\nResolve them and `git add` the files, then run `wt merge --continue`, or `wt merge --abort` to undo the merge",
+        action,
+        dir.display(),
+        conflicts.join("\n  ")
+    );
+}
 
-    // Build the strategy flag
-    let strategy_flag = match strategy {
-        "squash" => "--squash",
-        "merge" => "--merge",
-        "rebase" => "--rebase",
-        _ => bail!("Invalid merge strategy: {}. Use 'squash', 'merge', or 'rebase'", strategy),
+/// Put everything back the way it was before a merge stopped at `step`
+fn undo(main_worktree_path: &Path, state: &MergeState, step: Step) {
+    let result = match step {
+        Step::Rebasing if git::backend().rebase_in_progress(&state.worktree).unwrap_or(false) => {
+            run_git(&state.worktree, &["rebase", "--abort"])
+        }
+        Step::Rebasing => Ok(()),
+        _ => match &state.merge_dir {
+            Some(dir) => run_git(dir, &["reset", "--merge"]),
+            None => Ok(()),
+        },
     };
+    if let Err(err) = result {
+        eprintln!("Warning: Failed to undo the merge: {}", err);
+    }
+    // A finished rebase rewrote the branch, even if base never moved
+    if let Some(head) = &state.rebased_from {
+        let rebased = git::backend().commit(&state.worktree, "HEAD").is_ok_and(|current| current.hash != *head);
+        if rebased && !git::backend().rebase_in_progress(&state.worktree).unwrap_or(false) {
+            if let Err(err) = run_git(&state.worktree, &["reset", "--keep", head]) {
+                eprintln!("Warning: Failed to put {} back where it was: {}", state.branch, err);
+            }
+        }
+    }
+    if let (true, Some(dir)) = (state.temporary, &state.merge_dir) {
+        remove_temp_worktree(main_worktree_path, dir);
+    }
+}
 
-    // Step 1: Try to merge via PR first, fall back to local merge if no PR exists
-    eprintln!("Running: gh pr merge {}", strategy_flag);
-    let output = Command::new("gh")
-        .current_dir(&worktree_path)
-        .args(["pr", "merge", strategy_flag])
-        .output()
-        .context("Failed to execute gh pr merge. Is the GitHub CLI installed?")?;
+/// Finish a merge after conflicts were resolved or a cleanup step failed.
+/// A message or `--edit` given now replaces what the merge started with.
+fn continue_merge(config: &Config, main_worktree_path: &Path, mut state: MergeState, options: Options) -> Result<()> {
+    if options.message.is_some() {
+        state.message = options.message;
+    }
+    state.edit |= options.edit;
+    state.verify &= !options.no_verify;
+    state.force |= options.force;
+
+    // Resolving conflicts may have meant working inside these
+    let mut need_cd = state.need_cd || cwd_inside(&state.worktree);
+    if let (true, Some(dir)) = (state.temporary, &state.merge_dir) {
+        need_cd |= cwd_inside(dir);
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        if !stdout.is_empty() {
-            eprintln!("{}", stdout);
+    if state.step == Step::Rebasing {
+        if git::backend().rebase_in_progress(&state.worktree)? {
+            ensure_resolved(&state.worktree)?;
+            eprintln!("Continuing to rebase {} onto {}", state.branch, state.base());
+            if let Err(err) = run_git(&state.worktree, &["-c", "core.editor=true", "rebase", "--continue"]) {
+                return stop(main_worktree_path, &mut state, Step::Rebasing, err);
+            }
         }
-        if stderr.contains("no pull requests found") {
-            eprintln!("No PR found for branch \"{}\", merging locally.", branch_name);
-            merge_locally(&main_worktree_path, &branch_name, strategy)?;
-        } else {
-            bail!("Failed to merge PR: {}", stderr);
+        if state.verify {
+            verify(config, &state.worktree).context(
+                "Fix it and run `wt merge --continue`, or `wt merge --abort` to stop merging",
+            )?;
         }
-    } else {
-        // Print gh output
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        if !stdout.is_empty() {
-            eprintln!("{}", stdout);
+        land(config, main_worktree_path, &mut state)?;
+    } else if state.step == Step::Merging {
+        let dir = state.merge_dir.clone().context("Merge directory missing from merge state")?;
+        ensure_resolved(&dir)?;
+        if state.verify {
+            verify(config, &dir).context(
+                "Fix it and run `wt merge --continue`, or `wt merge --abort` to undo the merge",
+            )?;
         }
-        if !stderr.is_empty() {
-            eprintln!("{}", stderr);
+        eprintln!("Committing merge of {} into {}", state.branch, state.base());
+        commit(config, &dir, &state).context(
+            "Run `wt merge --continue` to try committing again, or `wt merge --abort` to undo the merge",
+        )?;
+        if state.temporary {
+            remove_temp_worktree(main_worktree_path, &dir);
         }
     }
 
-    // Step 2: Remove the worktree (this unlocks the branch for deletion)
-    eprintln!("Removing worktree: {}", worktree_path.display());
-    let output = Command::new("git")
-        .current_dir(&main_worktree_path)
-        .args(["worktree", "remove", worktree_path.to_str().unwrap()])
-        .output()
-        .context("Failed to execute git worktree remove")?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        bail!("Failed to remove worktree: {}", stderr);
+    state.need_cd = need_cd;
+    if state.step < Step::Merged {
+        state.advance(Step::Merged)?;
     }
+    finish(config, main_worktree_path, state)
+}
 
-    eprintln!("Worktree removed successfully");
+fn abort(main_worktree_path: &Path, state: MergeState) -> Result<()> {
+    if state.step >= Step::Merged {
+        eprintln!("{} is already merged, leaving the rest of the cleanup undone", state.branch);
+    } else {
+        undo(main_worktree_path, &state, state.step);
+        eprintln!("Merge of {} aborted", state.branch);
+    }
+    MergeState::clear()
+}
 
-    // Step 3: Delete the local branch (now possible since worktree is gone)
-    eprintln!("Deleting local branch: {}", branch_name);
-    let output = Command::new("git")
-        .current_dir(&main_worktree_path)
-        .args(["branch", "-D", &branch_name])
-        .output()
-        .context("Failed to execute git branch -D")?;
+fn cwd_inside(dir: &Path) -> bool {
+    env::current_dir().is_ok_and(|cwd| cwd.starts_with(dir))
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Warning: Failed to delete local branch: {}", stderr);
-        // Don't fail here - the branch might already be deleted or not exist
-    } else {
-        eprintln!("Local branch deleted");
+/// What to do when cleaning up after a merge fails part way
+const CLEANUP_HINT: &str = "The branch is merged, but cleaning up stopped. Fix it and run `wt merge --continue` \
+     (with --force to remove the worktree anyway), or `wt merge --abort` to leave the rest undone";
+
+/// The cleanup after a branch is merged, from wherever it last stopped
+fn finish(config: &Config, main_worktree_path: &Path, mut state: MergeState) -> Result<()> {
+    // Later git calls would fail once the directory we're in is removed
+    env::set_current_dir(main_worktree_path)
+        .with_context(|| format!("Failed to change to {}", main_worktree_path.display()))?;
+    let wt = worktrees::list()?.into_iter().find(|wt| wt.path == state.worktree);
+
+    if state.step == Step::Merged {
+        let hook_wt = wt.clone().unwrap_or_else(|| Worktree {
+            path: state.worktree.clone(),
+            branch: Some(state.branch.clone()),
+            ..Default::default()
+        });
+        hooks::run(config, Hook::PostMerge, &hook_wt, main_worktree_path).context(CLEANUP_HINT)?;
+        state.advance(Step::HookRun)?;
     }
 
-    // Step 4: Delete the remote branch (may already be deleted by GitHub's auto-delete setting)
-    eprintln!("Deleting remote branch: origin/{}", branch_name);
-    let output = Command::new("git")
-        .current_dir(&main_worktree_path)
-        .args(["push", "origin", "--delete", &branch_name])
-        .output()
-        .context("Failed to execute git push --delete")?;
+    if state.step == Step::HookRun {
+        // Removing the worktree unlocks the branch for deletion
+        match &wt {
+            Some(wt) => {
+                eprintln!("Removing worktree: {}", wt.path.display());
+                remove::remove_worktree(config, main_worktree_path, wt, state.force).context(CLEANUP_HINT)?;
+                eprintln!("Worktree removed successfully");
+            }
+            None => eprintln!("Worktree already removed: {}", state.worktree.display()),
+        }
+        state.advance(Step::WorktreeRemoved)?;
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        // This is expected if GitHub already auto-deleted the branch
-        if stderr.contains("remote ref does not exist") {
-            eprintln!("Remote branch already deleted (likely by GitHub auto-delete)");
+    if state.step == Step::WorktreeRemoved {
+        eprintln!("Deleting local branch: {}", state.branch);
+        let output = Command::new("git")
+            .current_dir(main_worktree_path)
+            .args(["branch", "-D", &state.branch])
+            .output()
+            .context("Failed to execute git branch -D")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            eprintln!("Warning: Failed to delete local branch: {}", stderr);
+            // Don't fail here - the branch might already be deleted or not exist
         } else {
-            eprintln!("Warning: Failed to delete remote branch: {}", stderr);
+            eprintln!("Local branch deleted");
+        }
+        state.advance(Step::BranchDeleted)?;
+    }
+
+    // Delete the remote branch (may already be deleted by the forge's auto-delete setting)
+    // A purely local repository has nothing to delete
+    let has_origin = git::backend().remote_url("origin").is_ok_and(|url| url.is_some());
+    let on_remote = match has_origin {
+        true => git::remote_has_branch(main_worktree_path, "origin", &state.branch),
+        false => Ok(false),
+    };
+    match on_remote {
+        Ok(false) if !has_origin => {}
+        Ok(false) => {
+            eprintln!("Remote branch already deleted (likely by the forge's auto-delete)");
+        }
+        Err(err) => {
+            eprintln!("Warning: Could not check remote branch: {}", err);
+        }
+        Ok(true) => {
+            eprintln!("Deleting remote branch: origin/{}", state.branch);
+            if let Err(err) = delete_remote_branch(config, main_worktree_path, &state.branch) {
+                eprintln!("Warning: Failed to delete remote branch: {}", err);
+            } else {
+                eprintln!("Remote branch deleted");
+            }
         }
-    } else {
-        eprintln!("Remote branch deleted");
     }
+    MergeState::clear()?;
 
     // If we were in the worktree being removed, cd to main
-    if need_cd {
+    if state.need_cd {
         eprintln!("Changing to main worktree: {}", main_worktree_path.display());
-        utils::print_cd_command(&main_worktree_path);
+        shell::cd(main_worktree_path)?;
     }
 
     eprintln!("Merge complete!");
 
     Ok(())
 }
+
+/// Delete `branch` from origin through its forge, or with `git push` when
+/// there's no forge to ask
+fn delete_remote_branch(config: &Config, main_worktree_path: &Path, branch: &str) -> Result<()> {
+    if let Some(forge) = forge::detect(config, main_worktree_path)? {
+        return forge.delete_branch(branch);
+    }
+    let output = Command::new("git")
+        .current_dir(main_worktree_path)
+        .args(["push", "origin", "--delete", branch])
+        .output()
+        .context("Failed to execute git push --delete")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+pub fn execute(name: Option<&str>, options: Options, resume: Option<Resume>) -> Result<()> {
+    let config = Config::load()?;
+    let main_worktree_path = utils::get_main_worktree_path()?;
+
+    match (MergeState::load()?, resume) {
+        (Some(state), Some(Resume::Continue)) => {
+            return continue_merge(&config, &main_worktree_path, state, options)
+        }
+        (Some(state), Some(Resume::Abort)) => return abort(&main_worktree_path, state),
+        (None, Some(_)) => bail!("No merge in progress"),
+        (Some(state), None) => bail!(
+            "A merge of {} is already in progress.\nRun `wt merge --continue` to finish it, or `wt merge --abort`",
+            state.branch
+        ),
+        (None, None) => {}
+    }
+
+    let strategy = options.strategy.as_deref().unwrap_or(&config.merge_strategy);
+    if (options.message.is_some() || options.edit) && strategy != "squash" {
+        bail!("--message and --edit only apply to squash merges");
+    }
+    let current = worktrees::current()?;
+    let is_main = current.path == main_worktree_path;
+
+    // Determine the worktree to merge
+    let (wt, need_cd) = match (is_main, name) {
+        // On main with a name: merge that worktree
+        (true, Some(n)) => (worktrees::get(&config, n)?, false),
+        // On main without a name: error
+        (true, None) => {
+            bail!("Must specify a worktree name when running from the main worktree.\nUsage: wt merge <worktree-name>");
+        }
+        // In a worktree without a name: merge current
+        (false, None) => (current, true),
+        // In a worktree with a name: merge that worktree (not current)
+        (false, Some(n)) => (worktrees::get(&config, n)?, false),
+    };
+
+    if wt.path == main_worktree_path {
+        bail!("Cannot merge the main worktree into itself.");
+    }
+    if wt.prunable.is_some() {
+        bail!("Worktree directory is missing: {}", wt.path.display());
+    }
+
+    // Get the branch name before we do anything (need it for cleanup later)
+    let Some(branch_name) = wt.branch.clone() else {
+        bail!("Worktree {} has no branch checked out (detached HEAD)", wt.path.display());
+    };
+
+    eprintln!("Merging and cleaning up worktree: {}", wt.path.display());
+    eprintln!("Branch: {}", branch_name);
+    // Unreadable metadata is left alone rather than overwritten
+    let mut meta = Meta::load(wt.name())
+        .map_err(|err| eprintln!("Warning: {:#}", err))
+        .ok();
+    if let Some(description) = meta.as_ref().and_then(|meta| meta.description.as_ref()) {
+        eprintln!("Description: {}", description);
+    }
+
+    if !["squash", "merge", "rebase"].contains(&strategy) {
+        bail!("Invalid merge strategy: {}. Use 'squash', 'merge', or 'rebase'", strategy);
+    }
+
+    let mut state = MergeState {
+        branch: branch_name,
+        worktree: wt.path.clone(),
+        strategy: strategy.to_string(),
+        base: None,
+        merge_dir: None,
+        rebased_from: None,
+        temporary: false,
+        message: options.message,
+        edit: options.edit,
+        verify: !options.no_verify,
+        need_cd,
+        force: options.force,
+        step: Step::Merged,
+    };
+
+    // Merge the PR if there is one, and only once it's ready
+    let forge = forge::detect(&config, &wt.path)?;
+    let pr = match &forge {
+        Some(forge) => forge.pull_request(&state.branch)?,
+        None => None,
+    };
+    // Remembered in case the merge stops or waits for auto-merge
+    if let (Some(pr), Some(meta)) = (&pr, &mut meta) {
+        meta.pull_request = Some(PullRequestLink::from(pr));
+        if let Err(err) = meta.save(wt.name()) {
+            eprintln!("Warning: {:#}", err);
+        }
+    }
+    let mut wait_for_auto_merge = false;
+    if let Some(pr) = pr.as_ref().filter(|pr| !pr.is_merged()) {
+        pr.print_summary();
+        let (hard, waiting): (Vec<_>, Vec<_>) = pr
+            .blockers()
+            .into_iter()
+            .partition(|blocker| matches!(blocker, Blocker::Hard(_)));
+        let reasons = |blockers: &[Blocker]| {
+            blockers
+                .iter()
+                .map(|(Blocker::Hard(reason) | Blocker::Waiting(reason))| format!("\n  - {}", reason))
+                .collect::<String>()
+        };
+        if !hard.is_empty() {
+            bail!("Not merging {}:{}{}", pr.reference, reasons(&hard), reasons(&waiting));
+        }
+        if !waiting.is_empty() && !options.auto {
+            bail!(
+                "Not merging {} yet:{}\nPass --auto to have it merged once it's ready",
+                pr.reference,
+                reasons(&waiting)
+            );
+        }
+        wait_for_auto_merge = !waiting.is_empty();
+    }
+
+    let already_merged = pr.as_ref().is_some_and(|pr| pr.is_merged());
+    if state.verify && !already_merged {
+        verify(&config, &wt.path)
+            .with_context(|| format!("Not merging {}; pass --no-verify to skip verification", state.branch))?;
+    }
+
+    match (&forge, &pr) {
+        (None, _) => {
+            eprintln!("No forge detected for origin, merging \"{}\" locally.", state.branch);
+            merge_locally(&config, &main_worktree_path, &mut state)?;
+        }
+        (Some(forge), None) => {
+            eprintln!(
+                "No pull request found for branch \"{}\" on {}, merging locally.",
+                state.branch,
+                forge.name()
+            );
+            merge_locally(&config, &main_worktree_path, &mut state)?;
+        }
+        (_, Some(pr)) if pr.is_merged() => eprintln!("{} is already merged", pr.reference),
+        (Some(forge), Some(pr)) => {
+            forge.merge(pr, strategy, wait_for_auto_merge)?;
+            if wait_for_auto_merge {
+                eprintln!(
+                    "Auto-merge enabled for {}. Once it merges, run `wt merge` again to clean up.",
+                    pr.reference
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    state.advance(Step::Merged)?;
+    finish(&config, &main_worktree_path, state)
+}
@@ -0,0 +1,131 @@
+//! What `wt` knows about a worktree beyond what git records.
+//!
+//! Each worktree's metadata is JSON in `<git-common-dir>/worktree-meta/<name>.json`,
+//! keyed by the worktree's directory name. `wt add` writes it, `wt rm`
+//! deletes it with the worktree, and `wt prune` deletes it for worktrees
+//! that are gone.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use crate::forge::{Issue, PullRequest};
+use crate::git;
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Meta {
+    /// When `wt add` created the worktree, as a Unix timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<i64>,
+    /// The branch `wt add` created the worktree for, which named its path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Who created the worktree, as `Name <email>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// The ref the branch started from, or the upstream it tracks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    /// The commit a new branch started at. Absent for branches that already
+    /// existed, track a remote branch or check out a pull request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_commit: Option<String>,
+    /// Set with `wt describe`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The issue the worktree was created for, with `wt add --issue`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issue: Option<Issue>,
+    /// The pull request checked out with `wt add --pr`, or opened with `wt pr`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<PullRequestLink>,
+}
+
+/// A pull request, as remembered for a worktree
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PullRequestLink {
+    pub number: u64,
+    /// How the forge refers to it, e.g. PR #12 or MR !12
+    pub reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl From<&PullRequest> for PullRequestLink {
+    fn from(pr: &PullRequest) -> Self {
+        PullRequestLink {
+            number: pr.number,
+            reference: pr.reference.clone(),
+            url: Some(pr.url.clone()),
+        }
+    }
+}
+
+impl Meta {
+    /// Metadata for a worktree created now, by the current git user
+    pub fn new() -> Meta {
+        Meta {
+            created: Some(chrono::Utc::now().timestamp()),
+            owner: git::backend().author().ok(),
+            ..Default::default()
+        }
+    }
+
+    fn path(name: &str) -> Result<PathBuf> {
+        Ok(git::backend().common_dir()?.join("worktree-meta").join(format!("{}.json", name)))
+    }
+
+    /// The metadata of worktree `name`, empty if none was saved
+    pub fn load(name: &str) -> Result<Meta> {
+        let path = Self::path(name)?;
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Meta::default()),
+            Err(err) => Err(err).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, name: &str) -> Result<()> {
+        let path = Self::path(name)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Forget worktree `name`
+    pub fn remove(name: &str) -> Result<()> {
+        let path = Self::path(name)?;
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Forget worktrees that are gone, e.g. after `git worktree prune`
+pub fn remove_missing() -> Result<()> {
+    let dir = git::backend().common_dir()?.join("worktree-meta");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    let worktrees = git::backend().worktrees()?;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !worktrees.iter().any(|wt| wt.name() == name) {
+            Meta::remove(name)?;
+        }
+    }
+    Ok(())
+}
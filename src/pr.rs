@@ -0,0 +1,179 @@
+use anyhow::{bail, Context, Result};
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use crate::config::Config;
+use crate::forge::{self, Issue, NewPullRequest, State};
+use crate::git;
+use crate::meta::{Meta, PullRequestLink};
+use crate::template::{self, PrFields};
+use crate::utils;
+use crate::worktrees;
+
+/// What `wt pr` was asked for on the command line
+pub struct Options {
+    pub title: Option<String>,
+    pub body: Option<String>,
+    /// Branch to open the PR against, instead of merge.base or the main branch
+    pub base: Option<String>,
+    pub draft: bool,
+    pub reviewers: Vec<String>,
+    pub labels: Vec<String>,
+    /// Open the PR in the browser as well as printing its URL
+    pub web: bool,
+}
+
+/// Title and body from the branch's commits, or from `pr.title` and `pr.body`
+fn describe(config: &Config, dir: &Path, base: &str, branch: &str) -> Result<(String, String)> {
+    let commits = git::backend()
+        .messages(dir, base, branch)
+        .with_context(|| format!("Failed to list commits on {}", branch))?;
+    let Some(first) = commits.first() else {
+        bail!("{} has no commits that aren't on {}", branch, base);
+    };
+    let list = commits
+        .iter()
+        .map(|commit| format!("* {}", commit.subject))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let fields = PrFields {
+        branch,
+        base,
+        subject: &first.subject,
+        body: &first.body,
+        commits: &list,
+        count: commits.len(),
+    };
+
+    let title = match &config.pr_title {
+        Some(template) => template::pull_request_text("pr.title", template, &fields)?,
+        None => first.subject.clone(),
+    };
+    let body = match &config.pr_body {
+        Some(template) => template::pull_request_text("pr.body", template, &fields)?,
+        None if commits.len() == 1 => first.body.clone(),
+        None => list,
+    };
+    Ok((title, body))
+}
+
+/// A line linking `issue`, which closes it on merge when it's on the forge
+fn issue_link(issue: &Issue) -> String {
+    match (issue.id.parse::<u64>(), &issue.url) {
+        (Ok(number), _) => format!("Closes #{}", number),
+        (Err(_), Some(url)) => format!("Issue: [{}]({})", issue.id, url),
+        (Err(_), None) => format!("Issue: {}", issue.id),
+    }
+}
+
+/// Push `branch` to origin, making it the branch's upstream unless it has one,
+/// like the pull request head a `wt add --pr` checkout pulls from
+fn push(dir: &Path, branch: &str) -> Result<()> {
+    eprintln!("Pushing {} to origin", branch);
+    let mut cmd = Command::new("git");
+    cmd.current_dir(dir).arg("push");
+    if git::backend().merge_ref(branch)?.is_none() {
+        cmd.arg("--set-upstream");
+    }
+    let status = cmd
+        .args(["origin", branch])
+        .stdout(Stdio::from(io::stderr()))
+        .status()
+        .context("Failed to execute git push")?;
+    if !status.success() {
+        bail!("Failed to push {} to origin", branch);
+    }
+    Ok(())
+}
+
+/// Open `url` in the default browser
+fn open(url: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    let status = Command::new(opener)
+        .arg(url)
+        .stdout(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to execute {}", opener))?;
+    if !status.success() {
+        bail!("{} failed to open {}", opener, url);
+    }
+    Ok(())
+}
+
+pub fn execute(name: Option<&str>, options: Options) -> Result<()> {
+    let config = Config::load()?;
+    let main_worktree_path = utils::get_main_worktree_path()?;
+
+    let wt = match name {
+        Some(name) => worktrees::get(&config, name)?,
+        None => worktrees::current()?,
+    };
+    if wt.path == main_worktree_path && name.is_none() {
+        bail!("Must specify a worktree name when running from the main worktree.\nUsage: wt pr <worktree-name>");
+    }
+    let Some(branch) = wt.branch.clone() else {
+        bail!("Worktree {} has no branch checked out (detached HEAD)", wt.path.display());
+    };
+
+    let Some(forge) = forge::detect(&config, &wt.path)? else {
+        bail!("No forge detected for origin. Set forge.type to say where pull requests live");
+    };
+    let base = match options.base.as_ref().or(config.merge_base.as_ref()) {
+        Some(base) => base.clone(),
+        None => utils::get_main_branch_name(&main_worktree_path)?,
+    };
+    if base == branch {
+        bail!("Cannot open a pull request from {} into itself", branch);
+    }
+
+    push(&wt.path, &branch)?;
+
+    // Unreadable metadata is left alone rather than overwritten
+    let mut meta = Meta::load(wt.name())
+        .map_err(|err| eprintln!("Warning: {:#}", err))
+        .ok();
+
+    // Closed and merged PRs stay on the forge; only an open one is reused
+    let pr = match forge.pull_request(&branch)? {
+        Some(pr) if pr.state == State::Open => {
+            eprintln!("{} already exists: {}", pr.reference, pr.title);
+            pr
+        }
+        _ => {
+            let (title, body) = describe(&config, &wt.path, &base, &branch)?;
+            let mut body = options.body.unwrap_or(body);
+            if let Some(issue) = meta.as_ref().and_then(|meta| meta.issue.as_ref()) {
+                let link = issue_link(issue);
+                if !body.contains(&link) {
+                    body = format!("{}\n\n{}", body, link).trim_start().to_string();
+                }
+            }
+            let new = NewPullRequest {
+                branch,
+                base,
+                title: options.title.unwrap_or(title),
+                body,
+                draft: options.draft,
+                reviewers: options.reviewers,
+                labels: options.labels,
+            };
+            eprintln!("Opening a pull request on {}: {}", forge.name(), new.title);
+            let pr = forge.create_pull_request(&new)?;
+            eprintln!("Created {}", pr.reference);
+            pr
+        }
+    };
+
+    if let Some(meta) = &mut meta {
+        meta.pull_request = Some(PullRequestLink::from(&pr));
+        if let Err(err) = meta.save(wt.name()) {
+            eprintln!("Warning: {:#}", err);
+        }
+    }
+
+    println!("{}", pr.url);
+    if options.web {
+        open(&pr.url)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::process::Command;
+use crate::config::Config;
+use crate::git::{self, GitBackend};
+use crate::meta::{self, Meta};
+use crate::remove;
+use crate::shell;
+use crate::utils;
+use crate::worktrees::{self, Worktree};
+
+/// Which stale worktrees to look for
+pub struct Filter {
+    /// Branches merged into the main branch, including squash and rebase merges
+    pub merged: bool,
+    /// Branches whose upstream was deleted from the remote
+    pub gone: bool,
+}
+
+/// Why a worktree is considered stale
+#[derive(Clone, Copy, PartialEq)]
+enum Reason {
+    Merged,
+    SquashMerged,
+    UpstreamGone,
+}
+
+impl Reason {
+    fn describe(self) -> &'static str {
+        match self {
+            Reason::Merged => "merged",
+            Reason::SquashMerged => "squash-merged",
+            Reason::UpstreamGone => "upstream gone",
+        }
+    }
+}
+
+struct Candidate {
+    wt: Worktree,
+    branch: String,
+    reason: Reason,
+}
+
+pub fn execute(filter: Filter, apply: bool, force: bool, fetch: bool) -> Result<()> {
+    let config = Config::load()?;
+    let main_worktree_path = utils::get_main_worktree_path()?;
+    let main_branch = utils::get_main_branch_name(&main_worktree_path)?;
+    let backend = git::backend();
+
+    if fetch {
+        fetch_and_prune(&main_worktree_path, backend.as_ref())?;
+    }
+
+    // A branch counts as merged if it landed on the local main branch or its upstream
+    let mut bases = vec![main_branch.clone()];
+    bases.extend(backend.upstream(&main_branch)?);
+
+    let current = worktrees::current().ok();
+    let mut candidates = Vec::new();
+    for wt in worktrees::list()?.into_iter().skip(1) {
+        let Some(branch) = wt.branch.clone() else {
+            continue;
+        };
+        if wt.prunable.is_some() || branch == main_branch {
+            continue;
+        }
+        if let Some(reason) = classify(backend.as_ref(), &wt, &branch, &bases, &filter)? {
+            candidates.push(Candidate { wt, branch, reason });
+        }
+    }
+
+    if candidates.is_empty() {
+        eprintln!("No merged or abandoned worktrees found");
+    } else {
+        eprintln!(
+            "{} {} worktree(s):",
+            if apply { "Removing" } else { "Would remove" },
+            candidates.len()
+        );
+        for candidate in &candidates {
+            eprintln!(
+                "  {:<30} {} ({})",
+                candidate.branch,
+                candidate.wt.path.display(),
+                candidate.reason.describe()
+            );
+        }
+    }
+
+    let mut removed_current = false;
+    let mut skipped = 0;
+    if apply {
+        // Later git commands would fail once the current worktree is gone
+        std::env::set_current_dir(&main_worktree_path)?;
+        for candidate in &candidates {
+            if let Some(reason) = &candidate.wt.locked {
+                eprintln!(
+                    "Skipping {}: worktree is locked{}",
+                    candidate.branch,
+                    if reason.is_empty() { String::new() } else { format!(": {}", reason) }
+                );
+                skipped += 1;
+                continue;
+            }
+
+            let mut risks = remove::assess(&candidate.wt, &main_worktree_path)?;
+            // Commits of a merged branch live on in the main branch under other hashes
+            if candidate.reason != Reason::UpstreamGone {
+                risks.unpushed.clear();
+            }
+            if !risks.is_empty() {
+                eprintln!("Worktree {} has work that only exists there:", candidate.wt.path.display());
+                risks.report();
+                if !force {
+                    eprintln!("Skipping {}. Use --force to remove it anyway.", candidate.branch);
+                    skipped += 1;
+                    continue;
+                }
+            }
+
+            remove::remove_worktree(&config, &main_worktree_path, &candidate.wt, force)?;
+            // --force only overrides work in the worktree; unpushed commits live on in the branch
+            if risks.unpushed.is_empty() {
+                remove::delete_local_branch(&main_worktree_path, &candidate.branch)?;
+            } else {
+                eprintln!(
+                    "Keeping branch '{}': {} commit(s) are neither pushed nor merged into the main branch",
+                    candidate.branch,
+                    risks.unpushed.len()
+                );
+            }
+            if current.as_ref().is_some_and(|wt| wt.path == candidate.wt.path) {
+                removed_current = true;
+            }
+        }
+    }
+
+    prune_missing(&main_worktree_path, apply)?;
+
+    if !apply && !candidates.is_empty() {
+        eprintln!("Run 'wt prune --yes' to remove them.");
+    }
+    if skipped > 0 {
+        eprintln!("Skipped {} worktree(s)", skipped);
+    }
+
+    if removed_current {
+        eprintln!("Changing to main worktree: {}", main_worktree_path.display());
+        shell::cd(&main_worktree_path)?;
+    }
+
+    Ok(())
+}
+
+/// Decide whether `branch`, checked out in `wt`, is stale according to `filter`
+fn classify(
+    backend: &dyn GitBackend,
+    wt: &Worktree,
+    branch: &str,
+    bases: &[String],
+    filter: &Filter,
+) -> Result<Option<Reason>> {
+    // A branch nobody has committed to yet is trivially "merged"
+    if filter.merged && !untouched(backend, wt, branch)? {
+        for base in bases {
+            if backend.is_ancestor(branch, base)? {
+                return Ok(Some(Reason::Merged));
+            }
+        }
+        for base in bases {
+            match git::is_squash_merged(branch, base) {
+                Ok(true) => return Ok(Some(Reason::SquashMerged)),
+                Ok(false) => {}
+                // One branch git can't compare shouldn't stop the whole prune
+                Err(err) => eprintln!("Warning: Could not tell whether {} is squash-merged: {:#}", branch, err),
+            }
+        }
+    }
+    if filter.gone && backend.upstream_gone(branch)? {
+        return Ok(Some(Reason::UpstreamGone));
+    }
+    Ok(None)
+}
+
+/// Whether `branch` still points at the commit `wt add` created it from
+fn untouched(backend: &dyn GitBackend, wt: &Worktree, branch: &str) -> Result<bool> {
+    let meta = Meta::load(wt.name()).unwrap_or_else(|err| {
+        eprintln!("Warning: {:#}", err);
+        Meta::default()
+    });
+    match meta.base_commit {
+        Some(base_commit) => Ok(backend.commit(&wt.path, branch)?.hash == base_commit),
+        // Without a record, go by the reflog. A branch tracking a remote branch
+        // holds someone's work even if it never moved here.
+        None => Ok(backend.branch_never_moved(branch)? && backend.upstream(branch)?.is_none()),
+    }
+}
+
+/// Update remote-tracking branches so deleted upstreams show up as gone
+fn fetch_and_prune(main_worktree_path: &Path, backend: &dyn GitBackend) -> Result<()> {
+    if backend.remotes()?.is_empty() {
+        return Ok(());
+    }
+
+    let output = Command::new("git")
+        .current_dir(main_worktree_path)
+        .args(["fetch", "--all", "--prune", "--quiet"])
+        .output()
+        .context("Failed to execute git fetch")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        eprintln!("Warning: failed to fetch, using remote-tracking branches as they are: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Clean up administrative data for worktrees whose directories were deleted by hand
+fn prune_missing(main_worktree_path: &Path, apply: bool) -> Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(main_worktree_path).args(["worktree", "prune", "--verbose"]);
+    if !apply {
+        cmd.arg("--dry-run");
+    }
+    let output = cmd.output().context("Failed to execute git worktree prune")?;
+
+    // git reports each pruned worktree on stderr
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        bail!("Failed to prune worktrees: {}", stderr);
+    }
+    for line in stderr.lines().filter(|line| !line.is_empty()) {
+        eprintln!("{}", line);
+    }
+    if apply {
+        meta::remove_missing()?;
+    }
+    Ok(())
+}
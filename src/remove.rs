@@ -1,30 +1,143 @@
 use anyhow::{bail, Context, Result};
+use std::path::Path;
 use std::process::Command;
+use crate::config::Config;
+use crate::git::{self, Commit, Stash};
+use crate::hooks::{self, Hook};
+use crate::meta::Meta;
+use crate::shell;
 use crate::utils;
+use crate::worktrees::{self, Worktree};
 
-pub fn execute(name: &str) -> Result<()> {
-    let worktree_path = if name == "." {
+/// How many untracked paths or commits to list before summarising the rest
+const REPORT_LIMIT: usize = 10;
+
+/// Work that only exists in a worktree or its branch
+pub struct Risks {
+    pub dirty: u32,
+    pub untracked: Vec<String>,
+    /// Commits neither pushed nor merged into the main branch
+    pub unpushed: Vec<Commit>,
+    /// Stashes made on the worktree's branch
+    pub stashes: Vec<Stash>,
+}
+
+impl Risks {
+    pub fn is_empty(&self) -> bool {
+        self.dirty == 0 && self.untracked.is_empty() && self.unpushed.is_empty() && self.stashes.is_empty()
+    }
+
+    pub fn report(&self) {
+        if self.dirty > 0 {
+            eprintln!("  {} uncommitted change(s)", self.dirty);
+        }
+        if !self.untracked.is_empty() {
+            eprintln!("  {} untracked file(s):", self.untracked.len());
+            report_list(self.untracked.iter().map(|path| path.to_string()));
+        }
+        if !self.unpushed.is_empty() {
+            eprintln!("  {} commit(s) neither pushed nor merged into the main branch:", self.unpushed.len());
+            report_list(self.unpushed.iter().map(|c| format!("{} {}", c.short_hash, c.subject)));
+        }
+        if !self.stashes.is_empty() {
+            eprintln!("  {} stash(es) made on this branch:", self.stashes.len());
+            report_list(self.stashes.iter().map(|s| format!("stash@{{{}}} {}", s.index, s.message)));
+        }
+    }
+}
+
+fn report_list(items: impl ExactSizeIterator<Item = String>) {
+    let total = items.len();
+    for item in items.take(REPORT_LIMIT) {
+        eprintln!("    {}", item);
+    }
+    if total > REPORT_LIMIT {
+        eprintln!("    ... and {} more", total - REPORT_LIMIT);
+    }
+}
+
+pub fn execute(name: &str, force: bool, delete_branch: bool) -> Result<()> {
+    let config = Config::load()?;
+    let main_worktree_path = utils::get_main_worktree_path()?;
+
+    let wt = if name == "." {
+        let wt = worktrees::current()?;
         // Check if we're in the main worktree
-        if utils::is_main_worktree()? {
+        if wt.path == main_worktree_path {
             bail!("Cannot remove the main worktree. Use 'rm <worktree-name>' to remove a specific worktree.");
         }
-
-        // Get current directory as the worktree to remove
-        let current_dir = utils::get_current_dir()?;
-        eprintln!("Removing current worktree: {}", current_dir.display());
-        current_dir
+        eprintln!("Removing current worktree: {}", wt.path.display());
+        wt
     } else {
-        // Construct the worktree path from the name
-        let root_dir = utils::get_root_dir()?;
-        let repo_name = utils::get_repo_name()?;
-        let worktree_path = root_dir.join(&repo_name).join(name);
-        eprintln!("Removing worktree: {}", worktree_path.display());
-        worktree_path
+        let wt = worktrees::get(&config, name)?;
+        if wt.path == main_worktree_path {
+            bail!("Cannot remove the main worktree.");
+        }
+        eprintln!("Removing worktree: {}", wt.path.display());
+        wt
     };
+    if let Some(description) = Meta::load(wt.name()).ok().and_then(|meta| meta.description) {
+        eprintln!("Description: {}", description);
+    }
 
-    // Remove the worktree
-    let output = Command::new("git")
-        .args(["worktree", "remove", worktree_path.to_str().unwrap()])
+    if let Some(reason) = &wt.locked {
+        bail!(
+            "Worktree is locked{}. Run 'git worktree unlock {}' first.",
+            if reason.is_empty() { String::new() } else { format!(": {}", reason) },
+            wt.path.display()
+        );
+    }
+
+    let risks = assess(&wt, &main_worktree_path)?;
+    if !risks.is_empty() {
+        eprintln!("Worktree {} has work that only exists there:", wt.path.display());
+        risks.report();
+        if !force {
+            bail!("Refusing to remove the worktree. Use --force to remove it anyway.");
+        }
+        eprintln!("Removing anyway (--force)");
+    }
+
+    remove_worktree(&config, &main_worktree_path, &wt, force)?;
+    eprintln!("Worktree removed successfully");
+
+    if delete_branch {
+        match &wt.branch {
+            // Anything unpushed would only survive in the branch, so keep it
+            Some(branch) if !risks.unpushed.is_empty() => {
+                eprintln!(
+                    "Keeping branch '{}': {} commit(s) are neither pushed nor merged into the main branch",
+                    branch,
+                    risks.unpushed.len()
+                );
+            }
+            Some(branch) => delete_local_branch(&main_worktree_path, branch)?,
+            None => eprintln!("Worktree had no branch checked out; nothing to delete"),
+        }
+    }
+
+    // If we removed the current worktree, cd to the main worktree
+    if name == "." {
+        eprintln!("Changing to main worktree: {}", main_worktree_path.display());
+        shell::cd(&main_worktree_path)?;
+    }
+
+    Ok(())
+}
+
+/// Run `git worktree remove` from the main worktree, with the pre-remove
+/// and post-remove hooks around it
+pub fn remove_worktree(config: &Config, main_worktree_path: &Path, wt: &Worktree, force: bool) -> Result<()> {
+    hooks::run(config, Hook::PreRemove, wt, main_worktree_path)
+        .with_context(|| format!("Keeping worktree {}", wt.path.display()))?;
+
+    let mut cmd = Command::new("git");
+    cmd.current_dir(main_worktree_path).args(["worktree", "remove"]);
+    if force {
+        cmd.arg("--force");
+    }
+    let output = cmd
+        .arg(wt.path.to_str().unwrap())
         .output()
         .context("Failed to execute git worktree remove")?;
 
@@ -33,15 +146,108 @@ pub fn execute(name: &str) -> Result<()> {
         bail!("Failed to remove worktree: {}", stderr);
     }
 
-    eprintln!("{}", String::from_utf8_lossy(&output.stderr));
-    eprintln!("Worktree removed successfully");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        eprintln!("{}", stderr.trim_end());
+    }
 
-    // If we removed the current worktree, cd to the main worktree
-    if name == "." {
-        let main_worktree_path = utils::get_main_worktree_path()?;
-        eprintln!("Changing to main worktree: {}", main_worktree_path.display());
-        utils::print_cd_command(&main_worktree_path);
+    if let Some(branch) = &wt.branch {
+        delete_pull_request_ref(main_worktree_path, branch);
+    }
+    if let Err(err) = Meta::remove(wt.name()) {
+        eprintln!("Warning: {:#}", err);
+    }
+
+    hooks::run(config, Hook::PostRemove, wt, main_worktree_path)
+}
+
+/// Delete the pull request head `wt add --pr` fetched for `branch`, if it
+/// was checked out that way. Failures only warn; the worktree is gone anyway.
+fn delete_pull_request_ref(main_worktree_path: &Path, branch: &str) {
+    let backend = git::backend();
+    let refname = match backend.merge_ref(branch) {
+        Ok(Some(refname)) => refname,
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("Warning: Could not read the upstream of {}: {}", branch, err);
+            return;
+        }
+    };
+    let fetched = git::PULL_REQUEST_REFS
+        .iter()
+        .any(|glob| refname.starts_with(glob.trim_end_matches('*')));
+    if !fetched || !backend.ref_exists(&refname).unwrap_or(false) {
+        return;
+    }
+
+    let output = Command::new("git")
+        .current_dir(main_worktree_path)
+        .args(["update-ref", "-d", &refname])
+        .output();
+    match output {
+        Ok(output) if output.status.success() => eprintln!("Deleted fetched {}", refname),
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            eprintln!("Warning: Failed to delete {}: {}", refname, stderr.trim());
+        }
+        Err(err) => eprintln!("Warning: Failed to delete {}: {}", refname, err),
+    }
+}
+
+/// Find everything that removing `wt` (and possibly its branch) could lose
+pub fn assess(wt: &Worktree, main_worktree_path: &Path) -> Result<Risks> {
+    let mut risks = Risks {
+        dirty: 0,
+        untracked: Vec::new(),
+        unpushed: Vec::new(),
+        stashes: Vec::new(),
+    };
+
+    // Nothing can be read from a worktree whose directory is already gone
+    if wt.prunable.is_some() {
+        return Ok(risks);
+    }
+
+    let backend = git::backend();
+
+    let status = backend.status(&wt.path).context("Failed to read worktree status")?;
+    risks.dirty = status.dirty;
+    risks.untracked = status.untracked;
+
+    // Compare against the main branch, or whatever the main worktree has checked out
+    let base = match utils::get_main_branch_name(main_worktree_path) {
+        Ok(branch) => branch,
+        Err(_) => backend.commit(main_worktree_path, "HEAD")?.short_hash,
+    };
+    risks.unpushed = backend
+        .unpushed_commits(&wt.path, &base)
+        .context("Failed to look for unpushed commits")?;
+
+    if let Some(branch) = &wt.branch {
+        risks.stashes = backend
+            .stashes()
+            .context("Failed to read the stash list")?
+            .into_iter()
+            .filter(|stash| stash.is_on_branch(branch))
+            .collect();
+    }
+
+    Ok(risks)
+}
+
+pub fn delete_local_branch(main_worktree_path: &Path, branch: &str) -> Result<()> {
+    // -D because git's own merged check only looks at HEAD and the upstream
+    let output = Command::new("git")
+        .current_dir(main_worktree_path)
+        .args(["branch", "-D", branch])
+        .output()
+        .context("Failed to execute git branch -D")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("Failed to delete branch '{}': {}", branch, stderr);
     }
 
+    eprintln!("Deleted branch '{}'", branch);
     Ok(())
 }
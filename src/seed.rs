@@ -0,0 +1,186 @@
+//! Seeding build directories like `target/` or `node_modules` from the main
+//! worktree, so builds in a new worktree start warm.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::copy;
+
+/// How files end up in the seeded directory, cheapest first. Never hard
+/// links: build tools rewrite files like cargo's fingerprints in place, which
+/// would change the main worktree's copy too.
+#[derive(Clone, Copy, PartialEq)]
+enum Method {
+    /// Copy-on-write clone (FICLONE on Btrfs/XFS, clonefile on APFS)
+    Reflink,
+    Copy,
+}
+
+impl Method {
+    fn name(self) -> &'static str {
+        match self {
+            Method::Reflink => "reflink",
+            Method::Copy => "copy",
+        }
+    }
+
+    fn transfer(self, src: &Path, dest: &Path) -> std::io::Result<()> {
+        match self {
+            Method::Reflink => reflink_copy::reflink(src, dest),
+            Method::Copy => fs::copy(src, dest).map(|_| ()),
+        }
+    }
+}
+
+/// Everything under a directory, as paths relative to it
+#[derive(Default)]
+struct Tree {
+    dirs: Vec<PathBuf>,
+    symlinks: Vec<PathBuf>,
+    /// Regular files with their sizes
+    files: Vec<(PathBuf, u64)>,
+}
+
+impl Tree {
+    fn scan(root: &Path) -> Result<Tree> {
+        let mut tree = Tree::default();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            let dir = root.join(&relative);
+            for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+                let entry = entry?;
+                let path = relative.join(entry.file_name());
+                let file_type = entry.file_type()?;
+                if file_type.is_symlink() {
+                    tree.symlinks.push(path);
+                } else if file_type.is_dir() {
+                    tree.dirs.push(path.clone());
+                    pending.push(path);
+                } else {
+                    tree.files.push((path, entry.metadata()?.len()));
+                }
+            }
+        }
+        Ok(tree)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.files.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// Seed each of `dirs` (relative to the worktree root) from the main worktree
+/// into `worktree_path`. Directories that are missing from the main worktree
+/// or already present in the new one are skipped.
+pub fn seed(dirs: &[String], main_worktree_path: &Path, worktree_path: &Path) -> Result<()> {
+    for dir in dirs {
+        let src = main_worktree_path.join(dir);
+        let dest = worktree_path.join(dir);
+        if !src.is_dir() {
+            eprintln!("Not seeding {}: no such directory in the main worktree", dir);
+            continue;
+        }
+        if dest.symlink_metadata().is_ok() {
+            eprintln!("Not seeding {}: it already exists in the new worktree", dir);
+            continue;
+        }
+        seed_dir(dir, &src, &dest).with_context(|| format!("Failed to seed {}", dir))?;
+    }
+    Ok(())
+}
+
+fn seed_dir(name: &str, src: &Path, dest: &Path) -> Result<()> {
+    let started = Instant::now();
+    let tree = Tree::scan(src)?;
+    let total = tree.bytes();
+    eprintln!("Seeding {} ({} in {} files)", name, format_bytes(total), tree.files.len());
+
+    fs::create_dir_all(dest)?;
+    for dir in &tree.dirs {
+        fs::create_dir_all(dest.join(dir))?;
+    }
+    for link in &tree.symlinks {
+        copy::symlink(&fs::read_link(src.join(link))?, &dest.join(link))?;
+    }
+
+    let Some(((first, first_size), rest)) = tree.files.split_first() else {
+        return Ok(());
+    };
+    let method = choose_method(&src.join(first), &dest.join(first))?;
+
+    let done = AtomicU64::new(*first_size);
+    let finished = AtomicBool::new(false);
+    thread::scope(|scope| -> Result<()> {
+        if std::io::stderr().is_terminal() {
+            scope.spawn(|| report_progress(name, &done, total, &finished));
+        }
+        let result = rest.par_iter().try_for_each(|(file, size)| {
+            method
+                .transfer(&src.join(file), &dest.join(file))
+                .with_context(|| format!("Failed to {} {}", method.name(), file.display()))?;
+            done.fetch_add(*size, Ordering::Relaxed);
+            Ok(())
+        });
+        finished.store(true, Ordering::Relaxed);
+        result
+    })?;
+
+    let saved = match method {
+        Method::Reflink => format!("{} saved", format_bytes(total)),
+        Method::Copy => "no space saved".to_string(),
+    };
+    eprintln!(
+        "Seeded {} by {} in {:.1}s ({})",
+        name,
+        method.name(),
+        started.elapsed().as_secs_f64(),
+        saved
+    );
+    Ok(())
+}
+
+/// Transfer the first file by reflink if this filesystem supports it, and use
+/// the same method for the rest
+fn choose_method(src: &Path, dest: &Path) -> Result<Method> {
+    if Method::Reflink.transfer(src, dest).is_ok() {
+        return Ok(Method::Reflink);
+    }
+    // A failed attempt can leave an empty file behind
+    let _ = fs::remove_file(dest);
+    Method::Copy
+        .transfer(src, dest)
+        .with_context(|| format!("Failed to copy {}", src.display()))?;
+    Ok(Method::Copy)
+}
+
+fn report_progress(name: &str, done: &AtomicU64, total: u64, finished: &AtomicBool) {
+    while !finished.load(Ordering::Relaxed) {
+        let done = done.load(Ordering::Relaxed);
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        eprint!("\r  {}: {} / {} ({}%)", name, format_bytes(done), format_bytes(total), percent);
+        thread::sleep(Duration::from_millis(200));
+    }
+    // Clear the progress line
+    eprint!("\r\x1b[2K");
+}
+
+/// Human-readable size, e.g. "1.2 GiB"
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
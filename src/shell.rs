@@ -0,0 +1,231 @@
+//! Integration with the user's shell.
+//!
+//! A child process can't change its parent shell's directory, so the `wt`
+//! function from `worktree shell-init` runs the binary with
+//! `WORKTREE_DIRECTIVE_FILE` naming a temp file. Commands append directives
+//! to it, quoted for the shell named in `WORKTREE_SHELL`, and the function
+//! applies them once the binary exits. stdout stays free for ordinary output.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+const DIRECTIVE_FILE_VAR: &str = "WORKTREE_DIRECTIVE_FILE";
+const SHELL_VAR: &str = "WORKTREE_SHELL";
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    #[value(alias = "nu")]
+    Nushell,
+    #[value(alias = "pwsh")]
+    Powershell,
+}
+
+/// Something for the shell wrapper to do after the binary exits
+enum Directive<'a> {
+    Cd(&'a Path),
+    Export { name: &'a str, value: &'a str },
+}
+
+/// Print the `wt` wrapper function for `shell`
+pub fn init(shell: Shell) -> Result<()> {
+    let script = match shell {
+        Shell::Bash => POSIX_INIT.replace("{shell}", "bash"),
+        Shell::Zsh => POSIX_INIT.replace("{shell}", "zsh"),
+        Shell::Fish => FISH_INIT.to_string(),
+        Shell::Nushell => NUSHELL_INIT.to_string(),
+        Shell::Powershell => POWERSHELL_INIT.to_string(),
+    };
+    print!("{}", script);
+    Ok(())
+}
+
+/// Change the shell's directory to `path` once the command finishes
+pub fn cd(path: &Path) -> Result<()> {
+    emit(Directive::Cd(path))
+}
+
+/// Set an environment variable in the shell once the command finishes
+// No command exports variables yet; every wrapper already applies them
+#[allow(dead_code)]
+pub fn export(name: &str, value: &str) -> Result<()> {
+    emit(Directive::Export { name, value })
+}
+
+fn emit(directive: Directive) -> Result<()> {
+    let Some(file) = env::var_os(DIRECTIVE_FILE_VAR) else {
+        // Wrappers from before shell-init eval any stdout line starting with "cd "
+        if let Directive::Cd(path) = directive {
+            println!("cd {}", quote_posix(&path.to_string_lossy()));
+        }
+        return Ok(());
+    };
+
+    let shell = env::var(SHELL_VAR)
+        .ok()
+        .and_then(|name| Shell::from_str(&name, true).ok())
+        .unwrap_or(Shell::Bash);
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&file)
+        .with_context(|| format!("Failed to open {}", Path::new(&file).display()))?;
+    writeln!(file, "{}", render(shell, &directive)).context("Failed to write shell directive")?;
+    Ok(())
+}
+
+/// Write `directive` in `shell`'s own syntax
+fn render(shell: Shell, directive: &Directive) -> String {
+    match (shell, directive) {
+        (Shell::Bash | Shell::Zsh, Directive::Cd(path)) => {
+            format!("cd -- {}", quote_posix(&path.to_string_lossy()))
+        }
+        (Shell::Bash | Shell::Zsh, Directive::Export { name, value }) => {
+            format!("export {}={}", name, quote_posix(value))
+        }
+        (Shell::Fish, Directive::Cd(path)) => format!("cd {}", quote_fish(&path.to_string_lossy())),
+        (Shell::Fish, Directive::Export { name, value }) => {
+            format!("set -gx {} {}", name, quote_fish(value))
+        }
+        // Nushell can't source a file chosen at runtime, so its wrapper parses JSON lines
+        (Shell::Nushell, Directive::Cd(path)) => {
+            serde_json::json!({ "action": "cd", "path": path.to_string_lossy() }).to_string()
+        }
+        (Shell::Nushell, Directive::Export { name, value }) => {
+            serde_json::json!({ "action": "export", "name": name, "value": value }).to_string()
+        }
+        (Shell::Powershell, Directive::Cd(path)) => {
+            format!("Set-Location -LiteralPath {}", quote_powershell(&path.to_string_lossy()))
+        }
+        (Shell::Powershell, Directive::Export { name, value }) => {
+            format!("$env:{} = {}", name, quote_powershell(value))
+        }
+    }
+}
+
+/// Single-quote for sh, bash and zsh: 'it'\''s'
+fn quote_posix(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Single-quote for fish, where only \ and ' are special: 'it\'s'
+fn quote_fish(s: &str) -> String {
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Single-quote for PowerShell: 'it''s'
+fn quote_powershell(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+const POSIX_INIT: &str = r#"wt() {
+    local directives exit_code
+    directives="$(mktemp "${TMPDIR:-/tmp}/wt.XXXXXX")" || return
+    WORKTREE_DIRECTIVE_FILE="$directives" WORKTREE_SHELL={shell} command worktree "$@"
+    exit_code=$?
+    if [ -s "$directives" ]; then
+        . "$directives"
+    fi
+    rm -f "$directives"
+    return $exit_code
+}
+"#;
+
+const FISH_INIT: &str = r#"function wt
+    set -l directives (mktemp)
+    or return
+    WORKTREE_DIRECTIVE_FILE=$directives WORKTREE_SHELL=fish command worktree $argv
+    set -l exit_code $status
+    if test -s $directives
+        source $directives
+    end
+    rm -f $directives
+    return $exit_code
+end
+"#;
+
+const NUSHELL_INIT: &str = r#"def --env --wrapped wt [...args] {
+    let directives = (mktemp -t wt.XXXXXX)
+    let failed = try {
+        with-env { WORKTREE_DIRECTIVE_FILE: $directives, WORKTREE_SHELL: nushell } { ^worktree ...$args }
+        false
+    } catch { true }
+    for directive in (open --raw $directives | lines | where $it != "" | each { from json }) {
+        match $directive.action {
+            "cd" => { cd $directive.path }
+            "export" => { load-env { ($directive.name): $directive.value } }
+        }
+    }
+    rm -f $directives
+    if $failed { error make --unspanned { msg: "worktree failed" } }
+}
+"#;
+
+const POWERSHELL_INIT: &str = r#"function wt {
+    $directives = Join-Path ([System.IO.Path]::GetTempPath()) "wt-$([guid]::NewGuid()).ps1"
+    $env:WORKTREE_DIRECTIVE_FILE = $directives
+    $env:WORKTREE_SHELL = 'powershell'
+    try {
+        & worktree @args
+    } finally {
+        Remove-Item Env:WORKTREE_DIRECTIVE_FILE, Env:WORKTREE_SHELL -ErrorAction SilentlyContinue
+    }
+    $exitCode = $LASTEXITCODE
+    if (Test-Path $directives) {
+        . $directives
+        Remove-Item $directives
+    }
+    $global:LASTEXITCODE = $exitCode
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "/tmp/it's a \\ \"path\"";
+    const VALUE: &str = "it's $HOME `x` \\ \"y\"";
+
+    fn cd(shell: Shell) -> String {
+        render(shell, &Directive::Cd(Path::new(PATH)))
+    }
+
+    fn export(shell: Shell) -> String {
+        render(shell, &Directive::Export { name: "WT_NAME", value: VALUE })
+    }
+
+    #[test]
+    fn posix_shells() {
+        for shell in [Shell::Bash, Shell::Zsh] {
+            assert_eq!(cd(shell), r#"cd -- '/tmp/it'\''s a \ "path"'"#);
+            assert_eq!(export(shell), r#"export WT_NAME='it'\''s $HOME `x` \ "y"'"#);
+        }
+    }
+
+    #[test]
+    fn fish() {
+        assert_eq!(cd(Shell::Fish), r#"cd '/tmp/it\'s a \\ "path"'"#);
+        assert_eq!(export(Shell::Fish), r#"set -gx WT_NAME 'it\'s $HOME `x` \\ "y"'"#);
+    }
+
+    #[test]
+    fn nushell() {
+        let cd: serde_json::Value = serde_json::from_str(&cd(Shell::Nushell)).unwrap();
+        assert_eq!(cd, serde_json::json!({ "action": "cd", "path": PATH }));
+        let export: serde_json::Value = serde_json::from_str(&export(Shell::Nushell)).unwrap();
+        assert_eq!(export, serde_json::json!({ "action": "export", "name": "WT_NAME", "value": VALUE }));
+    }
+
+    #[test]
+    fn powershell() {
+        assert_eq!(cd(Shell::Powershell), r#"Set-Location -LiteralPath '/tmp/it''s a \ "path"'"#);
+        assert_eq!(export(Shell::Powershell), r#"$env:WT_NAME = 'it''s $HOME `x` \ "y"'"#);
+    }
+}
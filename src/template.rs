@@ -0,0 +1,185 @@
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::path::PathBuf;
+use crate::config::{self, Config};
+use crate::git;
+use crate::utils;
+
+/// Placeholders understood by `path-template`
+pub const PLACEHOLDERS: &[&str] = &[
+    "root",
+    "repo",
+    "branch",
+    "branch_slug",
+    "name",
+    "owner",
+    "date",
+];
+
+/// Placeholders understood by `merge.squash-message`
+pub const SQUASH_PLACEHOLDERS: &[&str] = &["branch", "base", "subject", "count"];
+
+/// Placeholders understood by `pr.title` and `pr.body`
+pub const PR_PLACEHOLDERS: &[&str] = &["branch", "base", "subject", "body", "commits", "count"];
+
+/// Placeholders understood by `issue.branch`
+pub const ISSUE_PLACEHOLDERS: &[&str] = &["user", "issue", "slug"];
+
+/// Longest `{slug}` made from an issue title, so branch names stay readable
+const MAX_TITLE_SLUG: usize = 40;
+
+/// What a pull request's title and body can be built from
+pub struct PrFields<'a> {
+    pub branch: &'a str,
+    pub base: &'a str,
+    /// Subject of the branch's first commit
+    pub subject: &'a str,
+    /// Body of the branch's first commit
+    pub body: &'a str,
+    /// The subjects of all the branch's commits, as a bullet list
+    pub commits: &'a str,
+    pub count: usize,
+}
+
+/// Build the path for a new worktree of `branch` from the configured template
+pub fn worktree_path(config: &Config, branch: &str) -> Result<PathBuf> {
+    let rendered = render("path-template", &config.path_template, |placeholder| match placeholder {
+        "root" => Ok(config.root_dir.to_string_lossy().into_owned()),
+        "repo" => utils::get_repo_name(),
+        "branch" => Ok(branch.to_string()),
+        "branch_slug" | "name" => Ok(slugify(branch)),
+        "owner" => get_repo_owner(),
+        "date" => Ok(chrono::Local::now().format("%Y-%m-%d").to_string()),
+        _ => bail!(
+            "Unknown placeholder {{{}}} in path-template. Available: {}",
+            placeholder,
+            PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")
+        ),
+    })?;
+
+    Ok(config::expand_home(&rendered))
+}
+
+/// Build the title of a squash commit of `count` commits from `branch`, the first with `subject`
+pub fn squash_title(template: &str, branch: &str, base: &str, subject: &str, count: usize) -> Result<String> {
+    render("merge.squash-message", template, |placeholder| match placeholder {
+        "branch" => Ok(branch.to_string()),
+        "base" => Ok(base.to_string()),
+        "subject" => Ok(subject.to_string()),
+        "count" => Ok(count.to_string()),
+        _ => bail!(
+            "Unknown placeholder {{{}}} in merge.squash-message. Available: {}",
+            placeholder,
+            SQUASH_PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")
+        ),
+    })
+}
+
+/// Build a pull request's title or body from `template`, the value of `setting`
+pub fn pull_request_text(setting: &str, template: &str, fields: &PrFields) -> Result<String> {
+    render(setting, template, |placeholder| match placeholder {
+        "branch" => Ok(fields.branch.to_string()),
+        "base" => Ok(fields.base.to_string()),
+        "subject" => Ok(fields.subject.to_string()),
+        "body" => Ok(fields.body.to_string()),
+        "commits" => Ok(fields.commits.to_string()),
+        "count" => Ok(fields.count.to_string()),
+        _ => bail!(
+            "Unknown placeholder {{{}}} in {}. Available: {}",
+            placeholder,
+            setting,
+            PR_PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")
+        ),
+    })
+}
+
+/// Build the name of a branch for issue `id` titled `title` from `issue.branch`
+pub fn issue_branch(config: &Config, id: &str, title: &str) -> Result<String> {
+    render("issue.branch", &config.issue_branch, |placeholder| match placeholder {
+        "user" => get_user(),
+        "issue" => Ok(id.to_string()),
+        "slug" => Ok(title_slug(title)),
+        _ => bail!(
+            "Unknown placeholder {{{}}} in issue.branch. Available: {}",
+            placeholder,
+            ISSUE_PLACEHOLDERS.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")
+        ),
+    })
+}
+
+/// Turn an issue title into lowercase words joined by dashes, e.g.
+/// `Crash when saving (Windows)` -> `crash-when-saving-windows`
+pub fn title_slug(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()) {
+        if !slug.is_empty() && slug.len() + 1 + word.len() > MAX_TITLE_SLUG {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_lowercase());
+    }
+    // A single long word is cut rather than dropped
+    slug.truncate(MAX_TITLE_SLUG);
+    slug
+}
+
+/// Turn a branch name into a single path component, e.g. `feature/foo` -> `feature-foo`
+pub fn slugify(branch: &str) -> String {
+    let mut slug = String::with_capacity(branch.len());
+    for c in branch.chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches('-').to_string()
+}
+
+/// Substitute every `{placeholder}` in `template`, the value of `setting`, using `lookup`
+fn render(setting: &str, template: &str, lookup: impl Fn(&str) -> Result<String>) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .with_context(|| format!("Unclosed '{{' in {}: {}", setting, template))?;
+        rendered.push_str(&lookup(&rest[start + 1..start + end])?);
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+
+    Ok(rendered)
+}
+
+/// The user's handle: the part of their git email before the `@`, or their login name
+fn get_user() -> Result<String> {
+    let email = git::backend().config("user.email").ok().flatten();
+    let user = match email.as_deref().and_then(|email| email.split_once('@')) {
+        Some((user, _)) => user.to_string(),
+        None => env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .context("issue.branch uses {user} but neither user.email nor USER is set")?,
+    };
+    Ok(slugify(&user.to_lowercase()))
+}
+
+/// Get the owner (user or organisation) from the origin remote URL
+fn get_repo_owner() -> Result<String> {
+    let Some(url) = git::backend().remote_url("origin")? else {
+        bail!("path-template uses {{owner}} but the repository has no 'origin' remote");
+    };
+
+    // Handles both git@host:owner/repo.git and https://host/owner/repo.git
+    let path = url.trim_end_matches('/').trim_end_matches(".git");
+    let mut segments = path.rsplit(['/', ':']);
+    segments.next();
+    match segments.next() {
+        Some(owner) if !owner.is_empty() => Ok(owner.to_string()),
+        _ => bail!("Could not determine repository owner from remote URL: {}", url),
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{bail, Context, Result};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::path::{Path, PathBuf};
+use crate::config::Config;
+use crate::git;
+use crate::meta::Meta;
+use crate::template;
+use crate::utils;
+
+/// A worktree as reported by `git worktree list --porcelain`
+#[derive(Debug, Clone, Default)]
+pub struct Worktree {
+    pub path: PathBuf,
+    /// Commit checked out, absent for a bare repository
+    pub head: Option<String>,
+    /// Short branch name, absent when detached or bare
+    pub branch: Option<String>,
+    pub bare: bool,
+    pub detached: bool,
+    /// Set when locked, with the reason given (possibly empty)
+    pub locked: Option<String>,
+    /// Set when git considers the worktree prunable, with the reason
+    pub prunable: Option<String>,
+}
+
+impl Worktree {
+    /// The worktree's directory name
+    pub fn name(&self) -> &str {
+        self.path.file_name().and_then(|n| n.to_str()).unwrap_or("")
+    }
+}
+
+/// List every worktree of the current repository. The main worktree comes first.
+pub fn list() -> Result<Vec<Worktree>> {
+    git::backend().worktrees().context("Failed to list worktrees")
+}
+
+/// Find a worktree by directory name, branch name, the branch it was created
+/// for, or the path the configured template gives for `name`
+pub fn find(config: &Config, name: &str) -> Result<Option<Worktree>> {
+    let worktrees = list()?;
+    if let Some(wt) = worktrees.iter().find(|wt| wt.name() == name || wt.branch.as_deref() == Some(name)) {
+        return Ok(Some(wt.clone()));
+    }
+    // The branch checked out may have changed since, and a template with
+    // {date} gives another path every day
+    let main_worktree_path = utils::get_main_worktree_path()?;
+    let created_for = |wt: &&Worktree| {
+        wt.path != main_worktree_path && Meta::load(wt.name()).is_ok_and(|meta| meta.branch.as_deref() == Some(name))
+    };
+    if let Some(wt) = worktrees.iter().find(created_for) {
+        return Ok(Some(wt.clone()));
+    }
+
+    // The template may not apply here, e.g. {owner} without an origin remote
+    let Ok(expected_path) = template::worktree_path(config, name) else {
+        return Ok(None);
+    };
+    Ok(worktrees.into_iter().find(|wt| wt.path == expected_path))
+}
+
+/// Find a worktree by name, failing if there is none
+pub fn get(config: &Config, name: &str) -> Result<Worktree> {
+    match find(config, name)? {
+        Some(wt) => Ok(wt),
+        None => bail!("Worktree '{}' not found. Run 'wt list' to see available worktrees.", name),
+    }
+}
+
+/// Worktrees whose directory or branch name matches a partial `query`.
+/// Prefix matches win over substring matches, which win over fuzzy matches;
+/// only the best kind that matches anything is returned.
+pub fn search(worktrees: Vec<Worktree>, query: &str) -> Vec<Worktree> {
+    let query_lower = query.to_lowercase();
+    let names = |wt: &Worktree| {
+        let mut names = vec![wt.name().to_lowercase()];
+        names.extend(wt.branch.as_deref().map(str::to_lowercase));
+        names
+    };
+
+    let prefix: Vec<Worktree> = worktrees
+        .iter()
+        .filter(|wt| names(wt).iter().any(|name| name.starts_with(&query_lower)))
+        .cloned()
+        .collect();
+    if !prefix.is_empty() {
+        return prefix;
+    }
+
+    let substring: Vec<Worktree> = worktrees
+        .iter()
+        .filter(|wt| names(wt).iter().any(|name| name.contains(&query_lower)))
+        .cloned()
+        .collect();
+    if !substring.is_empty() {
+        return substring;
+    }
+
+    let matcher = SkimMatcherV2::default();
+    let mut scored: Vec<(i64, Worktree)> = worktrees
+        .into_iter()
+        .filter_map(|wt| {
+            let score = names(&wt).iter().filter_map(|name| matcher.fuzzy_match(name, query)).max()?;
+            Some((score, wt))
+        })
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, wt)| wt).collect()
+}
+
+/// The worktree containing the current directory
+pub fn current() -> Result<Worktree> {
+    let current_dir = utils::get_current_dir()?;
+    let worktrees = list()?;
+
+    worktrees
+        .into_iter()
+        .filter(|wt| contains(&wt.path, &current_dir))
+        // The main worktree may contain linked ones, so prefer the deepest match
+        .max_by_key(|wt| wt.path.components().count())
+        .context("The current directory is not inside a worktree")
+}
+
+fn contains(worktree_path: &Path, dir: &Path) -> bool {
+    let worktree_path = worktree_path.canonicalize().unwrap_or_else(|_| worktree_path.to_path_buf());
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    dir.starts_with(worktree_path)
+}
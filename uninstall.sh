@@ -59,6 +59,10 @@ remove_from_config() {
         skip && /^export PATH=.*\.local\/bin/ {
             next
         }
+        skip && /shell-init/ {
+            skip = 0
+            next
+        }
         skip && /^wt\(\)/ {
             in_function = 1
             next